@@ -0,0 +1,120 @@
+use ipnetwork::IpNetwork;
+use parking_lot::RwLock;
+use std::net::IpAddr;
+
+/// Action taken when a rule's range matches the address being checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuleAction {
+    Allow,
+    Deny,
+}
+
+/// A single ordered allow/deny rule over an IP/CIDR range.
+#[derive(Debug, Clone)]
+pub struct AccessRule {
+    pub network: IpNetwork,
+    pub action: RuleAction,
+}
+
+/// IP/CIDR access-control list consulted by the UDP proxy and the rate
+/// limiter before doing any per-packet/per-request work.
+///
+/// Rules are evaluated in order and the first matching range wins; an
+/// address matching no rule falls through to `default_action`. This is the
+/// same blacklist/whitelist shape DNS and shadowsocks-style proxies expose,
+/// which this crate previously had no equivalent of.
+pub struct AccessControl {
+    rules: RwLock<Vec<AccessRule>>,
+    default_action: RuleAction,
+}
+
+impl AccessControl {
+    pub fn new(default_action: RuleAction) -> Self {
+        Self {
+            rules: RwLock::new(Vec::new()),
+            default_action,
+        }
+    }
+
+    /// Replace the rule list wholesale, e.g. when the control plane pushes
+    /// a new blacklist/whitelist.
+    pub fn set_rules(&self, rules: Vec<AccessRule>) {
+        *self.rules.write() = rules;
+    }
+
+    /// Returns `true` if `ip` should be allowed through.
+    pub fn check(&self, ip: IpAddr) -> bool {
+        let rules = self.rules.read();
+        for rule in rules.iter() {
+            if rule.network.contains(ip) {
+                return rule.action == RuleAction::Allow;
+            }
+        }
+        self.default_action == RuleAction::Allow
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(cidr: &str, action: RuleAction) -> AccessRule {
+        AccessRule {
+            network: cidr.parse().unwrap(),
+            action,
+        }
+    }
+
+    #[test]
+    fn test_default_action_fallthrough() {
+        let ip: IpAddr = "203.0.113.1".parse().unwrap();
+
+        let allow_by_default = AccessControl::new(RuleAction::Allow);
+        assert!(allow_by_default.check(ip));
+
+        let deny_by_default = AccessControl::new(RuleAction::Deny);
+        assert!(!deny_by_default.check(ip));
+    }
+
+    #[test]
+    fn test_first_match_wins() {
+        let acl = AccessControl::new(RuleAction::Allow);
+        // A narrower deny rule listed before a broader allow rule should
+        // win for addresses it covers, even though the broader rule would
+        // also match.
+        acl.set_rules(vec![
+            rule("10.0.0.0/24", RuleAction::Deny),
+            rule("10.0.0.0/8", RuleAction::Allow),
+        ]);
+
+        let denied: IpAddr = "10.0.0.5".parse().unwrap();
+        let allowed: IpAddr = "10.1.2.3".parse().unwrap();
+
+        assert!(!acl.check(denied));
+        assert!(acl.check(allowed));
+    }
+
+    #[test]
+    fn test_overlapping_ranges_use_rule_order_not_specificity() {
+        let acl = AccessControl::new(RuleAction::Deny);
+        // The broader range is listed first, so it wins even for an
+        // address also covered by the narrower range later in the list -
+        // this is plain ordered matching, not longest-prefix-match.
+        acl.set_rules(vec![
+            rule("192.168.0.0/16", RuleAction::Allow),
+            rule("192.168.1.0/24", RuleAction::Deny),
+        ]);
+
+        let ip: IpAddr = "192.168.1.42".parse().unwrap();
+        assert!(acl.check(ip));
+    }
+
+    #[test]
+    fn test_no_matching_rule_falls_through_with_rules_present() {
+        let acl = AccessControl::new(RuleAction::Deny);
+        acl.set_rules(vec![rule("10.0.0.0/8", RuleAction::Allow)]);
+
+        let ip: IpAddr = "172.16.0.1".parse().unwrap();
+        assert!(!acl.check(ip));
+    }
+}
@@ -2,6 +2,21 @@ use parking_lot::RwLock;
 use std::collections::HashMap;
 use std::time::{Duration, Instant};
 
+use crate::config::BackendId;
+
+/// Number of one-second buckets tracked for the rolling error-rate window.
+const WINDOW_BUCKETS: usize = 10;
+const BUCKET_DURATION: Duration = Duration::from_secs(1);
+
+/// Minimum requests observed in the window before the error ratio is
+/// trusted enough to trip the circuit. Keeps a couple of unlucky calls on
+/// a quiet backend from opening it.
+const WINDOW_MIN_VOLUME: u32 = 10;
+
+/// Error ratio over the window at or above which the circuit opens
+/// regardless of the absolute consecutive-failure count.
+const WINDOW_ERROR_RATIO_THRESHOLD: f64 = 0.5;
+
 /// Circuit breaker states
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum CircuitState {
@@ -10,6 +25,12 @@ pub enum CircuitState {
     HalfOpen,    // Testing if backend recovered
 }
 
+#[derive(Debug, Clone, Copy, Default)]
+struct WindowBucket {
+    successes: u32,
+    failures: u32,
+}
+
 /// Circuit breaker for individual backends
 pub struct CircuitBreaker {
     state: CircuitState,
@@ -19,6 +40,12 @@ pub struct CircuitBreaker {
     error_threshold: u32,
     timeout: Duration,
     half_open_max_requests: u32,
+    // Rolling error-rate window: a ring of one-second buckets. Trips the
+    // circuit on sustained error ratio even when failures never reach
+    // `error_threshold` in a row (e.g. a backend flapping at 40% errors).
+    buckets: [WindowBucket; WINDOW_BUCKETS],
+    current_bucket: usize,
+    bucket_start: Instant,
 }
 
 impl CircuitBreaker {
@@ -31,6 +58,64 @@ impl CircuitBreaker {
             error_threshold,
             timeout,
             half_open_max_requests: 3,
+            buckets: [WindowBucket::default(); WINDOW_BUCKETS],
+            current_bucket: 0,
+            bucket_start: Instant::now(),
+        }
+    }
+
+    /// Roll the bucket ring forward to the current second, clearing any
+    /// buckets that have aged out of the window.
+    fn rotate_buckets(&mut self) {
+        let elapsed_buckets =
+            (self.bucket_start.elapsed().as_secs() / BUCKET_DURATION.as_secs()) as usize;
+        if elapsed_buckets == 0 {
+            return;
+        }
+
+        if elapsed_buckets >= WINDOW_BUCKETS {
+            self.buckets = [WindowBucket::default(); WINDOW_BUCKETS];
+        } else {
+            for i in 1..=elapsed_buckets {
+                let idx = (self.current_bucket + i) % WINDOW_BUCKETS;
+                self.buckets[idx] = WindowBucket::default();
+            }
+            self.current_bucket = (self.current_bucket + elapsed_buckets) % WINDOW_BUCKETS;
+        }
+        self.bucket_start = Instant::now();
+    }
+
+    fn window_counts(&self) -> (u32, u32) {
+        self.buckets
+            .iter()
+            .fold((0, 0), |(s, f), b| (s + b.successes, f + b.failures))
+    }
+
+    /// Trip the circuit if the rolling error ratio reaches the threshold
+    /// and enough volume has been observed to trust it.
+    fn evaluate_window(&mut self) {
+        let (successes, failures) = self.window_counts();
+        let total = successes + failures;
+        if total < WINDOW_MIN_VOLUME || self.state == CircuitState::Open {
+            return;
+        }
+
+        let ratio = failures as f64 / total as f64;
+        if ratio >= WINDOW_ERROR_RATIO_THRESHOLD {
+            self.transition_to_open();
+        }
+    }
+
+    /// Proactively move a long-`Open` breaker to `HalfOpen` once its
+    /// timeout has elapsed, so recovery doesn't wait on inbound traffic.
+    fn maybe_recover(&mut self) {
+        if self.state != CircuitState::Open {
+            return;
+        }
+        if let Some(last_failure) = self.last_failure_time {
+            if last_failure.elapsed() >= self.timeout {
+                self.transition_to_half_open();
+            }
         }
     }
 
@@ -60,6 +145,9 @@ impl CircuitBreaker {
 
     /// Record successful request
     pub fn record_success(&mut self) {
+        self.rotate_buckets();
+        self.buckets[self.current_bucket].successes += 1;
+
         match self.state {
             CircuitState::Closed => {
                 // Reset failure count on success
@@ -80,6 +168,9 @@ impl CircuitBreaker {
 
     /// Record failed request
     pub fn record_failure(&mut self) {
+        self.rotate_buckets();
+        self.buckets[self.current_bucket].failures += 1;
+
         match self.state {
             CircuitState::Closed => {
                 self.failure_count += 1;
@@ -97,6 +188,8 @@ impl CircuitBreaker {
                 self.last_failure_time = Some(Instant::now());
             }
         }
+
+        self.evaluate_window();
     }
 
     /// Get current circuit state
@@ -134,7 +227,7 @@ impl CircuitBreaker {
 
 /// Circuit breaker manager for all backends
 pub struct CircuitBreakerManager {
-    breakers: RwLock<HashMap<String, CircuitBreaker>>,
+    breakers: RwLock<HashMap<BackendId, CircuitBreaker>>,
     error_threshold: u32,
     timeout: Duration,
 }
@@ -149,52 +242,52 @@ impl CircuitBreakerManager {
     }
 
     /// Check if request to backend should be allowed
-    pub fn allow_request(&self, backend_addr: &str) -> bool {
+    pub fn allow_request(&self, backend: &BackendId) -> bool {
         let mut breakers = self.breakers.write();
         let breaker = breakers
-            .entry(backend_addr.to_string())
+            .entry(backend.clone())
             .or_insert_with(|| CircuitBreaker::new(self.error_threshold, self.timeout));
 
         breaker.allow_request()
     }
 
     /// Record successful request to backend
-    pub fn record_success(&self, backend_addr: &str) {
+    pub fn record_success(&self, backend: &BackendId) {
         let mut breakers = self.breakers.write();
-        if let Some(breaker) = breakers.get_mut(backend_addr) {
+        if let Some(breaker) = breakers.get_mut(backend) {
             breaker.record_success();
         }
     }
 
     /// Record failed request to backend
-    pub fn record_failure(&self, backend_addr: &str) {
+    pub fn record_failure(&self, backend: &BackendId) {
         let mut breakers = self.breakers.write();
         let breaker = breakers
-            .entry(backend_addr.to_string())
+            .entry(backend.clone())
             .or_insert_with(|| CircuitBreaker::new(self.error_threshold, self.timeout));
 
         breaker.record_failure();
     }
 
     /// Get state of specific backend circuit breaker
-    pub fn get_state(&self, backend_addr: &str) -> Option<CircuitState> {
+    pub fn get_state(&self, backend: &BackendId) -> Option<CircuitState> {
         let breakers = self.breakers.read();
-        breakers.get(backend_addr).map(|b| b.state())
+        breakers.get(backend).map(|b| b.state())
     }
 
     /// Get all circuit breaker states for monitoring
-    pub fn get_all_states(&self) -> HashMap<String, (CircuitState, u32)> {
+    pub fn get_all_states(&self) -> HashMap<BackendId, (CircuitState, u32)> {
         let breakers = self.breakers.read();
         breakers
             .iter()
-            .map(|(addr, breaker)| (addr.clone(), (breaker.state(), breaker.failure_count())))
+            .map(|(id, breaker)| (id.clone(), (breaker.state(), breaker.failure_count())))
             .collect()
     }
 
     /// Reset specific backend circuit breaker
-    pub fn reset_backend(&self, backend_addr: &str) {
+    pub fn reset_backend(&self, backend: &BackendId) {
         let mut breakers = self.breakers.write();
-        if let Some(breaker) = breakers.get_mut(backend_addr) {
+        if let Some(breaker) = breakers.get_mut(backend) {
             breaker.reset();
         }
     }
@@ -206,6 +299,21 @@ impl CircuitBreakerManager {
             breaker.reset();
         }
     }
+
+    /// Periodic maintenance pass, driven off the existing metrics interval.
+    ///
+    /// Rolls expired window buckets for every tracked backend so the error
+    /// ratio reflects recent traffic even between requests, and proactively
+    /// probes breakers that have sat `Open` past their timeout instead of
+    /// waiting for the next request to that backend to trigger recovery.
+    pub fn tick(&self) {
+        let mut breakers = self.breakers.write();
+        for breaker in breakers.values_mut() {
+            breaker.rotate_buckets();
+            breaker.evaluate_window();
+            breaker.maybe_recover();
+        }
+    }
 }
 
 #[cfg(test)]
@@ -259,18 +367,54 @@ mod tests {
     #[test]
     fn test_circuit_breaker_manager() {
         let manager = CircuitBreakerManager::new(2, 5);
+        let backend1 = BackendId::new("backend1");
+        let backend2 = BackendId::new("backend2");
 
         // Backend should start allowing requests
-        assert!(manager.allow_request("backend1"));
+        assert!(manager.allow_request(&backend1));
 
         // Record failures
-        manager.record_failure("backend1");
-        manager.record_failure("backend1");
+        manager.record_failure(&backend1);
+        manager.record_failure(&backend1);
 
         // Should be blocked
-        assert!(!manager.allow_request("backend1"));
+        assert!(!manager.allow_request(&backend1));
 
         // Different backend should still work
-        assert!(manager.allow_request("backend2"));
+        assert!(manager.allow_request(&backend2));
+    }
+
+    #[test]
+    fn test_circuit_breaker_window_trip() {
+        // A high absolute threshold so only the rolling error ratio can trip.
+        let mut breaker = CircuitBreaker::new(1000, Duration::from_secs(5));
+
+        for _ in 0..WINDOW_MIN_VOLUME {
+            breaker.record_success();
+        }
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        // Push failures past the minimum volume up to a 50% error ratio.
+        for _ in 0..WINDOW_MIN_VOLUME {
+            breaker.record_failure();
+        }
+
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn test_circuit_breaker_manager_tick_recovers() {
+        let manager = CircuitBreakerManager::new(1, 0);
+        let backend = BackendId::new("backend1");
+
+        manager.record_failure(&backend);
+        assert_eq!(manager.get_state(&backend), Some(CircuitState::Open));
+
+        // Timeout is effectively zero, so a maintenance tick should move
+        // the breaker straight to half-open without waiting on traffic.
+        std::thread::sleep(Duration::from_millis(10));
+        manager.tick();
+
+        assert_eq!(manager.get_state(&backend), Some(CircuitState::HalfOpen));
     }
 }
@@ -1,23 +1,98 @@
-use dashmap::DashMap;
 use parking_lot::RwLock;
 use std::sync::Arc;
-use tokio::sync::Notify;
+use std::time::{Duration, Instant};
+use tokio::sync::{watch, Notify};
+use tracing::warn;
 
+use crate::access_control::{AccessControl, RuleAction};
 use crate::circuit_breaker::CircuitBreakerManager;
 use crate::metrics::MetricsCollector;
-use crate::rate_limiter::RateLimiter;
+use crate::rate_limiter::{RateLimiter, SourceLimiter, SourceLimiterMode};
+use crate::supervisor::ConnectionSupervisor;
+
+/// Defaults for the per-source-IP accept-path limiter. The strategy itself
+/// is control-plane configurable via `ProxyConfig::rate_limit_mode`; these
+/// rps/burst/table-size knobs are not. See [`SourceLimiter`].
+const SOURCE_RATE_LIMIT_RPS: f64 = 50.0;
+const SOURCE_RATE_LIMIT_BURST: f64 = 100.0;
+const SOURCE_RATE_LIMIT_MAX_ENTRIES: usize = 100_000;
+const SOURCE_RATE_LIMIT_TTL: Duration = Duration::from_secs(300);
+
+/// Default action for the ACL when no rule matches. Deny rules pushed by
+/// the control plane act as a blacklist against this default-allow base.
+const ACCESS_CONTROL_DEFAULT_ACTION: RuleAction = RuleAction::Allow;
+
+/// Hard ceiling on how long `drain_connections` waits for in-flight
+/// connections to finish on their own before force-aborting whatever tasks
+/// are still running.
+const DRAIN_DEADLINE: Duration = Duration::from_secs(30);
+
+/// Default cap on concurrent connection tasks before `update_config` applies
+/// a control-plane-supplied value; see `ProxyConfig::max_connections`.
+const DEFAULT_MAX_CONNECTIONS: usize = 10_000;
 
 pub mod proxy {
     tonic::include_proto!("proxy");
 }
 
+/// Interned backend identifier, cheap to clone and hash.
+///
+/// Replaces passing raw `&str`/`String` addresses into the metrics and
+/// circuit-breaker hot paths, which previously forced a `to_string()`
+/// allocation on every request and made it easy to mix canonicalized and
+/// raw address forms. Backed by `Arc<str>` so clones are a refcount bump,
+/// not a copy.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BackendId(Arc<str>);
+
+impl BackendId {
+    pub fn new(address: impl AsRef<str>) -> Self {
+        Self(Arc::from(address.as_ref()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for BackendId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<&str> for BackendId {
+    fn from(s: &str) -> Self {
+        Self::new(s)
+    }
+}
+
+impl From<&String> for BackendId {
+    fn from(s: &String) -> Self {
+        Self::new(s)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Backend {
     pub address: String,
+    pub id: BackendId,
     pub weight: i32,
     pub healthy: bool,
 }
 
+impl Backend {
+    pub fn new(address: String, weight: i32, healthy: bool) -> Self {
+        let id = BackendId::new(&address);
+        Self {
+            address,
+            id,
+            weight,
+            healthy,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ProxyConfig {
     pub tcp_address: String,
@@ -26,6 +101,10 @@ pub struct ProxyConfig {
     pub udp_backends: Vec<Backend>,
     pub algorithm: String,
     pub session_affinity: bool,
+    /// EWMA RTT, in milliseconds, above which `least_rtt` treats a backend
+    /// as unhealthy and drains it from rotation. See
+    /// `LoadBalancer::with_max_backend_rtt`.
+    pub max_backend_rtt_ms: u64,
     pub rate_limit_rps: i32,
     pub rate_limit_burst: i32,
     pub connect_timeout_secs: i32,
@@ -33,65 +112,170 @@ pub struct ProxyConfig {
     pub read_timeout_secs: i32,
     pub circuit_breaker_threshold: u32,
     pub circuit_breaker_timeout_secs: u32,
+    /// Selects the per-source-IP accept-path limiter: `"token_bucket"`
+    /// (default), `"gcra"`/`"gcra_exact"`, or `"gcra_subnet"` to aggregate
+    /// by containing /24 (IPv4) or /64 (IPv6). See [`SourceLimiterMode`].
+    pub rate_limit_mode: String,
+    /// Max number of connection tasks the `ConnectionSupervisor` admits at
+    /// once; further connections are not accepted until one finishes.
+    pub max_connections: i32,
+    pub access_rules: Vec<crate::access_control::AccessRule>,
+    pub privdrop: crate::privdrop::PrivDropConfig,
+    pub tcp_keepalive: Option<crate::tcp_proxy::TcpKeepaliveConfig>,
+    pub tcp_fast_open: bool,
+    pub tcp_nodelay: bool,
+    /// Enables `UdpSession`'s WireGuard-style anti-replay window, which
+    /// reinterprets every datagram's leading 8 bytes as a big-endian
+    /// monotonic counter. Off by default: generic UDP proxying (DNS,
+    /// syslog, game/voice protocols, ...) has no such framing, so this is
+    /// only safe to turn on for a deployment where every client speaks a
+    /// protocol that embeds one.
+    pub udp_replay_protection: bool,
+    /// Bind address for the Prometheus scrape endpoint (`metrics::serve_metrics`).
+    pub metrics_bind_address: String,
+    /// Which backend source feeds `LoadBalancer::update_backends`:
+    /// `"grpc"` (default, pushed by `ProxyControlService`) or `"redis"`
+    /// (see the `discovery` module, gated behind the `redis-discovery`
+    /// feature).
+    pub discovery_backend: String,
+    #[cfg(feature = "redis-discovery")]
+    pub redis_discovery: Option<crate::discovery::RedisDiscoveryConfig>,
 }
 
 pub struct ProxyState {
     config: RwLock<Option<ProxyConfig>>,
     config_notify: Arc<Notify>,
-    active_connections: DashMap<u64, Arc<()>>,
-    connection_counter: parking_lot::Mutex<u64>,
-    draining: parking_lot::Mutex<bool>,
-    pub circuit_breaker: Arc<CircuitBreakerManager>,
-    pub rate_limiter: Arc<RateLimiter>,
+    /// Bumped on every `update_config`, so long-lived listeners (e.g. the
+    /// UDP data plane) can react to backend/algorithm changes without
+    /// restarting. The carried value is a generation counter, not the
+    /// config itself - subscribers re-fetch via `get_config()`.
+    config_version: watch::Sender<u64>,
+    /// Shutdown/drain signal. `false` normally, flipped to `true` once by
+    /// `drain_connections`; listeners (`tcp_proxy::run`'s accept loop,
+    /// `handle_connection`'s bidirectional copy) `select!` against
+    /// `changed()` instead of polling `is_draining()`.
+    shutdown: watch::Sender<bool>,
+    /// Hot-swapped by `update_config` on every reconfiguration. Held behind
+    /// a lock (rather than rebuilt via a raw pointer cast) since a write
+    /// here can race with ordinary reads from the TCP/UDP accept loops -
+    /// `RwLock<Arc<T>>` makes that swap a normal, sound write instead of a
+    /// data race. Accessed through the getter of the same name below.
+    circuit_breaker: RwLock<Arc<CircuitBreakerManager>>,
+    rate_limiter: RwLock<Arc<RateLimiter>>,
+    source_rate_limiter: RwLock<Arc<SourceLimiter>>,
+    pub access_control: Arc<AccessControl>,
     pub metrics: Arc<MetricsCollector>,
+    /// Owns every spawned connection task: enforces max-in-flight
+    /// backpressure, tracks `JoinHandle`s for `drain_connections`, and
+    /// observes task panics. Replaces the old fire-and-forget
+    /// `tokio::spawn` plus per-connection `active_connections` bookkeeping.
+    /// Hot-swapped by `update_config`; see the comment on `circuit_breaker`.
+    connection_supervisor: RwLock<Arc<ConnectionSupervisor>>,
 }
 
 impl ProxyState {
     pub fn new() -> Self {
         // Initialize with default values - will be updated via config
         let default_circuit_breaker = Arc::new(CircuitBreakerManager::new(5, 30));
-        let default_rate_limiter = Arc::new(RateLimiter::new(1000, 100));
+        let access_control = Arc::new(AccessControl::new(ACCESS_CONTROL_DEFAULT_ACTION));
+        let default_rate_limiter = Arc::new(
+            RateLimiter::new(1000, 100).with_access_control(access_control.clone()),
+        );
+        let source_rate_limiter = Arc::new(SourceLimiter::new(
+            SourceLimiterMode::TokenBucket,
+            SOURCE_RATE_LIMIT_RPS,
+            SOURCE_RATE_LIMIT_BURST,
+            SOURCE_RATE_LIMIT_MAX_ENTRIES,
+            SOURCE_RATE_LIMIT_TTL,
+        ));
         let metrics = Arc::new(MetricsCollector::new());
+        let connection_supervisor = Arc::new(ConnectionSupervisor::new(
+            DEFAULT_MAX_CONNECTIONS,
+            metrics.clone(),
+        ));
+        let (config_version, _) = watch::channel(0u64);
+        let (shutdown, _) = watch::channel(false);
 
         Self {
             config: RwLock::new(None),
             config_notify: Arc::new(Notify::new()),
-            active_connections: DashMap::new(),
-            connection_counter: parking_lot::Mutex::new(0),
-            draining: parking_lot::Mutex::new(false),
-            circuit_breaker: default_circuit_breaker,
-            rate_limiter: default_rate_limiter,
+            config_version,
+            shutdown,
+            circuit_breaker: RwLock::new(default_circuit_breaker),
+            rate_limiter: RwLock::new(default_rate_limiter),
+            source_rate_limiter: RwLock::new(source_rate_limiter),
+            access_control,
             metrics,
+            connection_supervisor: RwLock::new(connection_supervisor),
         }
     }
 
+    /// Current circuit breaker manager. Cloning the `Arc` out from behind
+    /// the lock keeps callers lock-free for the duration of their use of it.
+    pub fn circuit_breaker(&self) -> Arc<CircuitBreakerManager> {
+        self.circuit_breaker.read().clone()
+    }
+
+    pub fn rate_limiter(&self) -> Arc<RateLimiter> {
+        self.rate_limiter.read().clone()
+    }
+
+    pub fn source_rate_limiter(&self) -> Arc<SourceLimiter> {
+        self.source_rate_limiter.read().clone()
+    }
+
+    pub fn connection_supervisor(&self) -> Arc<ConnectionSupervisor> {
+        self.connection_supervisor.read().clone()
+    }
+
     pub fn update_config(&self, config: ProxyConfig) {
         // Update circuit breaker and rate limiter based on new config
         let circuit_breaker = Arc::new(CircuitBreakerManager::new(
             config.circuit_breaker_threshold,
             config.circuit_breaker_timeout_secs,
         ));
-        let rate_limiter = Arc::new(RateLimiter::new(
-            config.rate_limit_rps as u64,
-            config.rate_limit_burst as u64,
+        let rate_limiter = Arc::new(
+            RateLimiter::new(config.rate_limit_rps as u64, config.rate_limit_burst as u64)
+                .with_access_control(self.access_control.clone()),
+        );
+        let connection_supervisor = Arc::new(ConnectionSupervisor::new(
+            config.max_connections.max(1) as usize,
+            self.metrics.clone(),
+        ));
+        let source_rate_limiter = Arc::new(SourceLimiter::new(
+            SourceLimiterMode::from_str(&config.rate_limit_mode),
+            SOURCE_RATE_LIMIT_RPS,
+            SOURCE_RATE_LIMIT_BURST,
+            SOURCE_RATE_LIMIT_MAX_ENTRIES,
+            SOURCE_RATE_LIMIT_TTL,
         ));
 
-        // Replace the circuit breaker and rate limiter
-        // Note: This is safe because we're using Arc
-        unsafe {
-            let self_mut = self as *const Self as *mut Self;
-            (*self_mut).circuit_breaker = circuit_breaker;
-            (*self_mut).rate_limiter = rate_limiter;
-        }
+        // Replace the circuit breaker, rate limiter, connection supervisor
+        // and source limiter under their own locks - a plain field write
+        // through a raw pointer cast would race with the ordinary reads
+        // `circuit_breaker()` et al. do from other threads.
+        *self.circuit_breaker.write() = circuit_breaker;
+        *self.rate_limiter.write() = rate_limiter;
+        *self.connection_supervisor.write() = connection_supervisor;
+        *self.source_rate_limiter.write() = source_rate_limiter;
+
+        self.access_control.set_rules(config.access_rules.clone());
 
         *self.config.write() = Some(config);
         self.config_notify.notify_waiters();
+        self.config_version.send_modify(|generation| *generation += 1);
     }
 
     pub fn get_config(&self) -> Option<ProxyConfig> {
         self.config.read().clone()
     }
 
+    /// Subscribe to config generation bumps. The carried value is just a
+    /// counter - call `get_config()` after a change to get the new snapshot.
+    pub fn subscribe_config(&self) -> watch::Receiver<u64> {
+        self.config_version.subscribe()
+    }
+
     pub async fn is_configured(&self) -> bool {
         self.config.read().is_some()
     }
@@ -102,38 +286,42 @@ impl ProxyState {
         }
     }
 
-    pub fn register_connection(&self) -> (u64, Arc<()>) {
-        let mut counter = self.connection_counter.lock();
-        *counter += 1;
-        let id = *counter;
-        let token = Arc::new(());
-        self.active_connections.insert(id, token.clone());
-        (id, token)
-    }
-
-    pub fn unregister_connection(&self, id: u64) {
-        self.active_connections.remove(&id);
-    }
-
-    pub fn active_connection_count(&self) -> usize {
-        self.active_connections.len()
+    pub fn is_draining(&self) -> bool {
+        *self.shutdown.borrow()
     }
 
-    pub fn is_draining(&self) -> bool {
-        *self.draining.lock()
+    /// Subscribe to the shutdown/drain signal. `changed()` resolves once
+    /// `drain_connections` broadcasts; accept loops and in-flight
+    /// connection handlers `select!` against it to stop promptly instead of
+    /// only noticing between blocking I/O calls.
+    pub fn subscribe_shutdown(&self) -> watch::Receiver<bool> {
+        self.shutdown.subscribe()
     }
 
+    /// Broadcast the shutdown signal, wait for the connection supervisor's
+    /// live tasks to drain on their own up to `DRAIN_DEADLINE`, then
+    /// force-abort whatever connection tasks are still running.
     pub async fn drain_connections(&self) {
-        *self.draining.lock() = true;
+        let _ = self.shutdown.send(true);
+
+        let connection_supervisor = self.connection_supervisor();
+        let deadline = Instant::now() + DRAIN_DEADLINE;
+        while connection_supervisor.live_task_count() > 0 && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
 
-        // Wait for all active connections to finish
-        while self.active_connection_count() > 0 {
-            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        let remaining = connection_supervisor.live_task_count();
+        if remaining > 0 {
+            warn!(
+                "Drain deadline exceeded with {} connection(s) still active, forcing cancellation",
+                remaining
+            );
+            connection_supervisor.abort_all();
         }
     }
 
     pub fn reset_draining(&self) {
-        *self.draining.lock() = false;
+        let _ = self.shutdown.send(false);
     }
 
     pub fn get_metrics(&self) -> Arc<MetricsCollector> {
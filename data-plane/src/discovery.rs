@@ -0,0 +1,161 @@
+//! Redis-backed dynamic backend discovery, as an alternative to pushing
+//! backend updates over the gRPC `ProxyControlService`. Gated behind the
+//! `redis-discovery` cargo feature so the `redis` dependency is optional for
+//! deployments that only ever use the control plane.
+#![cfg(feature = "redis-discovery")]
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::StreamExt;
+use tracing::{error, info, warn};
+
+use crate::config::Backend;
+use crate::load_balancer::LoadBalancer;
+
+/// How often `poll_loop` re-reads the backend set when pub/sub isn't
+/// available (or after the subscription drops).
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Settings for the Redis discovery source.
+#[derive(Debug, Clone)]
+pub struct RedisDiscoveryConfig {
+    pub redis_url: String,
+    /// Redis set key holding `"addr weight"` entries, one per backend.
+    pub key: String,
+    pub poll_interval: Duration,
+}
+
+impl RedisDiscoveryConfig {
+    pub fn new(redis_url: String, key: String) -> Self {
+        Self {
+            redis_url,
+            key,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+        }
+    }
+}
+
+/// Drive `load_balancer`'s backend set from Redis. Reads `config.key` as a
+/// Redis set on startup and calls `LoadBalancer::update_backends`, then
+/// subscribes to `"{key}:changes"` and repeats the read on every
+/// notification, falling back to polling at `config.poll_interval` if the
+/// subscription can't be established or later ends. Returns an error only
+/// if the client can't be created or the initial read fails; after that it
+/// keeps polling, logging failed refreshes, and does not return.
+pub async fn run(
+    config: RedisDiscoveryConfig,
+    load_balancer: Arc<LoadBalancer>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = redis::Client::open(config.redis_url.as_str())?;
+
+    refresh(&client, &config.key, &load_balancer).await?;
+
+    let channel = format!("{}:changes", config.key);
+    match client.get_async_connection().await {
+        Ok(conn) => {
+            if let Err(e) = subscribe_loop(conn, &channel, &client, &config.key, &load_balancer).await {
+                warn!(
+                    "Redis discovery pub/sub subscription ended ({}), falling back to polling",
+                    e
+                );
+                poll_loop(client, config, load_balancer).await
+            } else {
+                Ok(())
+            }
+        }
+        Err(e) => {
+            warn!(
+                "Failed to open Redis pub/sub connection ({}), falling back to polling",
+                e
+            );
+            poll_loop(client, config, load_balancer).await
+        }
+    }
+}
+
+async fn subscribe_loop(
+    conn: redis::aio::Connection,
+    channel: &str,
+    client: &redis::Client,
+    key: &str,
+    load_balancer: &Arc<LoadBalancer>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut pubsub = conn.into_pubsub();
+    pubsub.subscribe(channel).await?;
+    let mut messages = pubsub.on_message();
+
+    info!("Subscribed to Redis discovery channel {}", channel);
+
+    loop {
+        if messages.next().await.is_none() {
+            return Err("Redis pub/sub stream closed".into());
+        }
+
+        if let Err(e) = refresh(client, key, load_balancer).await {
+            error!("Failed to refresh backends from Redis: {}", e);
+        }
+    }
+}
+
+async fn poll_loop(
+    client: redis::Client,
+    config: RedisDiscoveryConfig,
+    load_balancer: Arc<LoadBalancer>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut interval = tokio::time::interval(config.poll_interval);
+    loop {
+        interval.tick().await;
+        if let Err(e) = refresh(&client, &config.key, &load_balancer).await {
+            error!("Failed to refresh backends from Redis: {}", e);
+        }
+    }
+}
+
+async fn refresh(
+    client: &redis::Client,
+    key: &str,
+    load_balancer: &Arc<LoadBalancer>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut conn = client.get_async_connection().await?;
+    let entries: Vec<String> = redis::cmd("SMEMBERS").arg(key).query_async(&mut conn).await?;
+
+    let backends = parse_backends(&entries);
+    info!(
+        "Redis discovery refreshed {} backend(s) from key {}",
+        backends.len(),
+        key
+    );
+    load_balancer.update_backends(backends);
+    Ok(())
+}
+
+/// Parse `"addr weight"` entries (weight optional, defaults to 1) into
+/// healthy `Backend`s. Malformed entries are skipped with a warning rather
+/// than failing the whole refresh.
+fn parse_backends(entries: &[String]) -> Vec<Backend> {
+    entries
+        .iter()
+        .filter_map(|entry| {
+            let mut parts = entry.split_whitespace();
+            let addr = match parts.next() {
+                Some(addr) => addr,
+                None => {
+                    warn!("Skipping empty Redis discovery entry");
+                    return None;
+                }
+            };
+            let weight = match parts.next() {
+                None => 1,
+                Some(w) => match w.parse::<i32>() {
+                    Ok(weight) => weight,
+                    Err(_) => {
+                        warn!("Skipping malformed Redis discovery entry {:?}", entry);
+                        return None;
+                    }
+                },
+            };
+            Some(Backend::new(addr.to_string(), weight, true))
+        })
+        .collect()
+}
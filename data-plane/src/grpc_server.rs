@@ -2,7 +2,12 @@ use std::sync::Arc;
 use tonic::{Request, Response, Status};
 use tracing::{info, warn};
 
+use crate::access_control::{AccessRule, RuleAction};
 use crate::config::{proxy, Backend, ProxyConfig, ProxyState};
+#[cfg(feature = "redis-discovery")]
+use crate::discovery::RedisDiscoveryConfig;
+use crate::privdrop::PrivDropConfig;
+use crate::tcp_proxy::TcpKeepaliveConfig;
 
 pub struct ProxyControlService {
     state: Arc<ProxyState>,
@@ -36,17 +41,19 @@ impl proxy::proxy_control_server::ProxyControl for ProxyControlService {
             udp_address: pb_config.listen.as_ref()
                 .map(|l| l.udp_address.clone())
                 .unwrap_or_default(),
-            backends: pb_config.backends.iter().map(|b| Backend {
-                address: b.address.clone(),
-                weight: b.weight,
-                healthy: b.healthy,
-            }).collect(),
+            backends: pb_config.backends.iter()
+                .map(|b| Backend::new(b.address.clone(), b.weight, b.healthy))
+                .collect(),
             algorithm: pb_config.load_balancing.as_ref()
                 .map(|lb| lb.algorithm.clone())
                 .unwrap_or_else(|| "round_robin".to_string()),
             session_affinity: pb_config.load_balancing.as_ref()
                 .map(|lb| lb.session_affinity)
                 .unwrap_or(false),
+            max_backend_rtt_ms: pb_config.load_balancing.as_ref()
+                .map(|lb| lb.max_backend_rtt_ms)
+                .filter(|ms| *ms > 0)
+                .unwrap_or(500),
             rate_limit_rps: pb_config.traffic.as_ref()
                 .and_then(|t| t.rate_limit.as_ref())
                 .map(|rl| rl.requests_per_second)
@@ -55,6 +62,10 @@ impl proxy::proxy_control_server::ProxyControl for ProxyControlService {
                 .and_then(|t| t.rate_limit.as_ref())
                 .map(|rl| rl.burst)
                 .unwrap_or(100),
+            rate_limit_mode: pb_config.traffic.as_ref()
+                .and_then(|t| t.rate_limit.as_ref())
+                .map(|rl| rl.mode.clone())
+                .unwrap_or_else(|| "token_bucket".to_string()),
             connect_timeout_secs: pb_config.traffic.as_ref()
                 .and_then(|t| t.timeout.as_ref())
                 .map(|to| to.connect_seconds)
@@ -67,6 +78,51 @@ impl proxy::proxy_control_server::ProxyControl for ProxyControlService {
                 .and_then(|t| t.timeout.as_ref())
                 .map(|to| to.read_seconds)
                 .unwrap_or(30),
+            max_connections: pb_config.traffic.as_ref()
+                .map(|t| t.max_connections)
+                .unwrap_or(10_000),
+            access_rules: pb_config.access_control.as_ref()
+                .map(|ac| ac.rules.iter().filter_map(|r| {
+                    r.cidr.parse().ok().map(|network| AccessRule {
+                        network,
+                        action: if r.deny { RuleAction::Deny } else { RuleAction::Allow },
+                    })
+                }).collect())
+                .unwrap_or_default(),
+            privdrop: pb_config.privilege_drop.as_ref()
+                .map(|pd| PrivDropConfig {
+                    user: if pd.user.is_empty() { None } else { Some(pd.user.clone()) },
+                    group: if pd.group.is_empty() { None } else { Some(pd.group.clone()) },
+                    chroot_dir: if pd.chroot_dir.is_empty() { None } else { Some(pd.chroot_dir.clone()) },
+                })
+                .unwrap_or_default(),
+            discovery_backend: pb_config.discovery.as_ref()
+                .map(|d| d.backend.clone())
+                .unwrap_or_else(|| "grpc".to_string()),
+            #[cfg(feature = "redis-discovery")]
+            redis_discovery: pb_config.discovery.as_ref()
+                .filter(|d| d.backend == "redis")
+                .map(|d| RedisDiscoveryConfig::new(d.redis_url.clone(), d.redis_key.clone())),
+            tcp_keepalive: pb_config.tcp_tuning.as_ref()
+                .filter(|t| t.keepalive_enabled)
+                .map(|t| TcpKeepaliveConfig {
+                    idle_secs: t.keepalive_idle_secs,
+                    interval_secs: t.keepalive_interval_secs,
+                    count: t.keepalive_count,
+                }),
+            tcp_fast_open: pb_config.tcp_tuning.as_ref()
+                .map(|t| t.fast_open)
+                .unwrap_or(false),
+            tcp_nodelay: pb_config.tcp_tuning.as_ref()
+                .map(|t| t.nodelay)
+                .unwrap_or(true),
+            udp_replay_protection: pb_config.udp_tuning.as_ref()
+                .map(|u| u.replay_protection)
+                .unwrap_or(false),
+            metrics_bind_address: pb_config.metrics.as_ref()
+                .map(|m| m.bind_address.clone())
+                .filter(|addr| !addr.is_empty())
+                .unwrap_or_else(|| "0.0.0.0:9090".to_string()),
         };
 
         info!("Configured {} backends on TCP:{}, UDP:{}",
@@ -93,11 +149,9 @@ impl proxy::proxy_control_server::ProxyControl for ProxyControlService {
         let mut config = self.state.get_config()
             .ok_or_else(|| Status::failed_precondition("Proxy not configured"))?;
 
-        config.backends = backend_list.backends.iter().map(|b| Backend {
-            address: b.address.clone(),
-            weight: b.weight,
-            healthy: b.healthy,
-        }).collect();
+        config.backends = backend_list.backends.iter()
+            .map(|b| Backend::new(b.address.clone(), b.weight, b.healthy))
+            .collect();
 
         self.state.update_config(config);
 
@@ -116,17 +170,17 @@ impl proxy::proxy_control_server::ProxyControl for ProxyControlService {
         
         info!("Draining connections with timeout: {}s", drain_req.timeout_seconds);
 
-        let active_before = self.state.active_connection_count();
-        
+        let active_before = self.state.connection_supervisor().live_task_count();
+
         // Start draining
         let state = self.state.clone();
         let timeout = tokio::time::Duration::from_secs(drain_req.timeout_seconds as u64);
-        
+
         tokio::time::timeout(timeout, async move {
             state.drain_connections().await;
         }).await.ok();
 
-        let active_after = self.state.active_connection_count();
+        let active_after = self.state.connection_supervisor().live_task_count();
         let drained = active_before.saturating_sub(active_after);
 
         info!("Drained {} connections ({} remaining)", drained, active_after);
@@ -145,9 +199,19 @@ impl proxy::proxy_control_server::ProxyControl for ProxyControlService {
     ) -> Result<Response<Self::StreamMetricsStream>, Status> {
         let mut stream = request.into_inner();
         let (tx, rx) = tokio::sync::mpsc::channel(100);
+        let metrics = self.state.metrics.clone();
 
         tokio::spawn(async move {
-            while let Ok(Some(_metrics)) = stream.message().await {
+            let mut previous: Option<proxy::MetricsData> = None;
+            while let Ok(Some(sample)) = stream.message().await {
+                // Fold the remote node's sample into our own registry so a
+                // scraper hitting this node sees an aggregate view across
+                // the control plane, not just this node's local traffic.
+                // Samples are cumulative running totals, so only the delta
+                // since this peer's last report on this stream is folded in.
+                metrics.record_external_sample(&sample, previous.as_ref());
+                previous = Some(sample);
+
                 // Acknowledge receipt
                 if tx.send(Ok(proxy::MetricsAck { received: true })).await.is_err() {
                     warn!("Failed to send metrics ack");
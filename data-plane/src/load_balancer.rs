@@ -1,16 +1,44 @@
-use parking_lot::RwLock;
-use std::collections::HashMap;
-use std::hash::{Hash, Hasher};
+use dashmap::DashMap;
+use parking_lot::{Mutex, RwLock};
+use std::collections::{BTreeMap, HashMap};
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use crate::config::Backend;
 
+/// Smoothing factor for the per-backend RTT EWMA: weight given to each new
+/// sample versus the running average.
+const LEAST_RTT_ALPHA: f64 = 0.2;
+
+/// EWMA RTT above which a backend is treated as unhealthy by `least_rtt`
+/// and drained from rotation, even if its health check still says healthy.
+const DEFAULT_MAX_BACKEND_RTT: Duration = Duration::from_millis(500);
+
+/// Virtual nodes contributed to the consistent-hash ring per unit of backend
+/// weight. Higher counts smooth out the distribution at the cost of a larger
+/// ring to build and search.
+const CONSISTENT_HASH_VNODES_PER_WEIGHT: usize = 40;
+
+/// Time constant (seconds) for the Peak-EWMA per-backend latency decay:
+/// roughly the window over which an old sample's influence fades, so a
+/// backend that's gone quiet responds to a fresh sample almost immediately
+/// instead of dragging a stale average.
+const PEAK_EWMA_TAU_SECS: f64 = 10.0;
+
 /// Load balancing algorithms for distributing traffic across backends
 pub enum Algorithm {
     RoundRobin,
     LeastConnections,
     WeightedRoundRobin,
     ConsistentHash,
+    LeastRtt,
+    /// Scores each backend by `(active_connections + 1) * ewma_rtt / weight`
+    /// and picks the minimum - latency-aware load across weighted backends.
+    PeakEwma,
+    /// `active_connections / weight`; like `LeastConnections` but weight-aware,
+    /// for deployments that don't want latency sensitivity.
+    WeightedLeastConnections,
 }
 
 impl Algorithm {
@@ -19,6 +47,9 @@ impl Algorithm {
             "least_connections" => Algorithm::LeastConnections,
             "weighted_round_robin" | "weighted" => Algorithm::WeightedRoundRobin,
             "consistent_hash" => Algorithm::ConsistentHash,
+            "least_rtt" | "latency" => Algorithm::LeastRtt,
+            "peak_ewma" => Algorithm::PeakEwma,
+            "weighted_least_connections" => Algorithm::WeightedLeastConnections,
             _ => Algorithm::RoundRobin,
         }
     }
@@ -28,12 +59,27 @@ pub struct LoadBalancer {
     backends: RwLock<Vec<BackendWithStats>>,
     algorithm: Algorithm,
     round_robin_counter: AtomicUsize,
+    // EWMA of RTT in milliseconds, keyed by backend address and stored as
+    // the raw bits of an f64 since atomics don't support floats directly.
+    rtt_ewma: DashMap<String, AtomicU64>,
+    max_backend_rtt: Duration,
+    // Cached consistent-hash ring, keyed by `backends_version` so it's only
+    // rebuilt when the backend set actually changes rather than on every
+    // selection.
+    ring_cache: RwLock<Option<(u64, Arc<BTreeMap<u64, String>>)>>,
+    backends_version: AtomicU64,
 }
 
 /// Backend with connection tracking for least-connections algorithm
 pub struct BackendWithStats {
     pub backend: Backend,
     pub active_connections: AtomicU64,
+    // Peak-EWMA latency, in milliseconds, stored as raw f64 bits since
+    // atomics don't support floats directly. `0` means no sample has
+    // landed yet. Distinct from `LoadBalancer::rtt_ewma` (used by
+    // `LeastRtt`), which decays per-sample rather than by elapsed time.
+    ewma_rtt_ms: AtomicU64,
+    ewma_last_update: Mutex<Option<Instant>>,
 }
 
 impl Clone for BackendWithStats {
@@ -41,17 +87,32 @@ impl Clone for BackendWithStats {
         Self {
             backend: self.backend.clone(),
             active_connections: AtomicU64::new(self.active_connections.load(Ordering::Relaxed)),
+            ewma_rtt_ms: AtomicU64::new(self.ewma_rtt_ms.load(Ordering::Relaxed)),
+            ewma_last_update: Mutex::new(*self.ewma_last_update.lock()),
         }
     }
 }
 
 impl LoadBalancer {
     pub fn new(backends: Vec<Backend>, algorithm: String) -> Self {
+        Self::with_max_backend_rtt(backends, algorithm, DEFAULT_MAX_BACKEND_RTT)
+    }
+
+    /// Like [`Self::new`], but with an operator-supplied ceiling on
+    /// per-backend EWMA RTT (see [`Self::max_backend_rtt`]) instead of
+    /// `DEFAULT_MAX_BACKEND_RTT`.
+    pub fn with_max_backend_rtt(
+        backends: Vec<Backend>,
+        algorithm: String,
+        max_backend_rtt: Duration,
+    ) -> Self {
         let backends_with_stats = backends
             .into_iter()
             .map(|b| BackendWithStats {
                 backend: b,
                 active_connections: AtomicU64::new(0),
+                ewma_rtt_ms: AtomicU64::new(0),
+                ewma_last_update: Mutex::new(None),
             })
             .collect();
 
@@ -59,6 +120,10 @@ impl LoadBalancer {
             backends: RwLock::new(backends_with_stats),
             algorithm: Algorithm::from_str(&algorithm),
             round_robin_counter: AtomicUsize::new(0),
+            rtt_ewma: DashMap::new(),
+            max_backend_rtt,
+            ring_cache: RwLock::new(None),
+            backends_version: AtomicU64::new(0),
         }
     }
 
@@ -81,6 +146,9 @@ impl LoadBalancer {
             Algorithm::LeastConnections => self.least_connections(&healthy),
             Algorithm::WeightedRoundRobin => self.weighted_round_robin(&healthy),
             Algorithm::ConsistentHash => self.consistent_hash(&healthy, context),
+            Algorithm::LeastRtt => self.least_rtt(&healthy),
+            Algorithm::PeakEwma => self.peak_ewma(&healthy),
+            Algorithm::WeightedLeastConnections => self.weighted_least_connections(&healthy),
         }
     }
 
@@ -143,23 +211,236 @@ impl LoadBalancer {
         self.round_robin(backends)
     }
 
-    /// Consistent hashing for session affinity
+    /// Ketama-style consistent hashing for session affinity.
+    ///
+    /// Hashes the context onto a ring of virtual nodes (built from the full,
+    /// not just healthy, backend set) and walks forward from the first vnode
+    /// at or past that point, wrapping around the ring, skipping any vnode
+    /// that currently maps to an unhealthy backend. Because only the handful
+    /// of vnodes between the removed backend's neighbors move, adding or
+    /// removing one backend only remaps its share of keys instead of
+    /// everyone's, unlike a plain `hash % len`.
     fn consistent_hash(&self, backends: &[&BackendWithStats], context: Option<&str>) -> Option<Backend> {
         if backends.is_empty() {
             return None;
         }
 
-        // Use context (e.g., client IP) for hash, or fall back to round-robin
-        let hash_value = if let Some(ctx) = context {
-            let mut hasher = std::collections::hash_map::DefaultHasher::new();
-            ctx.hash(&mut hasher);
-            hasher.finish() as usize
-        } else {
+        let ctx = match context {
+            Some(ctx) => ctx,
+            None => return self.round_robin(backends),
+        };
+
+        let ring = self.ring();
+        if ring.is_empty() {
             return self.round_robin(backends);
+        }
+
+        let hash_value = stable_hash(ctx);
+
+        let start_key = match ring.range(hash_value..).next() {
+            Some((key, _)) => *key,
+            None => *ring.keys().next().expect("ring is non-empty"),
         };
 
-        let index = hash_value % backends.len();
-        Some(backends[index].backend.clone())
+        // Walk forward from `start_key`, wrapping to the front of the ring,
+        // until we hit a vnode whose backend is in the healthy set.
+        let ordered = ring.range(start_key..).chain(ring.range(..start_key));
+        for (_, addr) in ordered.take(ring.len()) {
+            if let Some(backend) = backends.iter().find(|b| &b.backend.address == addr) {
+                return Some(backend.backend.clone());
+            }
+        }
+
+        // Every vnode we walked pointed at a backend outside the healthy
+        // set passed in (shouldn't happen in practice); fall back safely.
+        self.round_robin(backends)
+    }
+
+    /// Return the cached consistent-hash ring, rebuilding it if the backend
+    /// set has changed since it was last built.
+    fn ring(&self) -> Arc<BTreeMap<u64, String>> {
+        let version = self.backends_version.load(Ordering::Acquire);
+
+        if let Some((cached_version, ring)) = self.ring_cache.read().as_ref() {
+            if *cached_version == version {
+                return ring.clone();
+            }
+        }
+
+        let backends = self.backends.read();
+        let mut ring = BTreeMap::new();
+        for backend in backends.iter() {
+            let vnodes =
+                CONSISTENT_HASH_VNODES_PER_WEIGHT * backend.backend.weight.max(1) as usize;
+            for i in 0..vnodes {
+                let key = stable_hash(&format!("{}#{}", backend.backend.address, i));
+                ring.insert(key, backend.backend.address.clone());
+            }
+        }
+        drop(backends);
+
+        let ring = Arc::new(ring);
+        *self.ring_cache.write() = Some((version, ring.clone()));
+        ring
+    }
+
+    /// Least-RTT: select the healthy backend with the lowest EWMA round-trip
+    /// latency, skipping any whose EWMA has crept past `max_backend_rtt`.
+    /// Backends with no samples yet are round-robined among themselves so
+    /// they get a chance to accumulate one.
+    fn least_rtt(&self, backends: &[&BackendWithStats]) -> Option<Backend> {
+        if backends.is_empty() {
+            return None;
+        }
+
+        let max_rtt_ms = self.max_backend_rtt.as_secs_f64() * 1000.0;
+        let mut best: Option<(&BackendWithStats, f64)> = None;
+        let mut unseen = Vec::new();
+
+        for backend in backends {
+            match self.rtt_ewma.get(&backend.backend.address) {
+                Some(rtt_bits) => {
+                    let rtt_ms = f64::from_bits(rtt_bits.load(Ordering::Relaxed));
+                    if rtt_ms > max_rtt_ms {
+                        continue;
+                    }
+                    if best.map_or(true, |(_, best_rtt)| rtt_ms < best_rtt) {
+                        best = Some((backend, rtt_ms));
+                    }
+                }
+                None => unseen.push(*backend),
+            }
+        }
+
+        if let Some((backend, _)) = best {
+            return Some(backend.backend.clone());
+        }
+
+        if !unseen.is_empty() {
+            return self.round_robin(&unseen);
+        }
+
+        // Every backend is over the RTT threshold; fall back to round-robin
+        // rather than refusing traffic outright.
+        self.round_robin(backends)
+    }
+
+    /// Fold a freshly observed RTT sample into a backend's EWMA.
+    pub fn record_backend_latency(&self, backend_addr: &str, rtt: Duration) {
+        let sample_ms = rtt.as_secs_f64() * 1000.0;
+
+        let entry = self
+            .rtt_ewma
+            .entry(backend_addr.to_string())
+            .or_insert_with(|| AtomicU64::new(sample_ms.to_bits()));
+
+        let mut current_bits = entry.load(Ordering::Relaxed);
+        loop {
+            let current_ms = f64::from_bits(current_bits);
+            let updated_ms = LEAST_RTT_ALPHA * sample_ms + (1.0 - LEAST_RTT_ALPHA) * current_ms;
+            match entry.compare_exchange_weak(
+                current_bits,
+                updated_ms.to_bits(),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(actual) => current_bits = actual,
+            }
+        }
+    }
+
+    /// Peak-EWMA: score each healthy backend by
+    /// `(active_connections + 1) * ewma_rtt_ms / weight` and pick the
+    /// minimum, so load is weighted by both how busy a backend is and how
+    /// slow it's been responding; the `+ 1` keeps latency in the score when
+    /// backends are idle. Backends with no latency sample yet are treated as
+    /// a nominal 1ms so they aren't starved while warming up.
+    fn peak_ewma(&self, backends: &[&BackendWithStats]) -> Option<Backend> {
+        if backends.is_empty() {
+            return None;
+        }
+
+        const UNSAMPLED_RTT_MS: f64 = 1.0;
+
+        let mut best: Option<(&BackendWithStats, f64)> = None;
+        for backend in backends {
+            let weight = backend.backend.weight.max(1) as f64;
+            let rtt_ms = f64::from_bits(backend.ewma_rtt_ms.load(Ordering::Relaxed));
+            let rtt_ms = if rtt_ms <= 0.0 { UNSAMPLED_RTT_MS } else { rtt_ms };
+            let active = backend.active_connections.load(Ordering::Relaxed) as f64;
+            let score = (active + 1.0) * rtt_ms / weight;
+
+            if best.map_or(true, |(_, best_score)| score < best_score) {
+                best = Some((backend, score));
+            }
+        }
+
+        best.map(|(backend, _)| backend.backend.clone())
+    }
+
+    /// Weighted least-connections: `active_connections / weight`, minimized.
+    /// Like `least_connections` but accounts for backend weight, without the
+    /// latency sensitivity of `peak_ewma`.
+    fn weighted_least_connections(&self, backends: &[&BackendWithStats]) -> Option<Backend> {
+        if backends.is_empty() {
+            return None;
+        }
+
+        let mut best: Option<(&BackendWithStats, f64)> = None;
+        for backend in backends {
+            let weight = backend.backend.weight.max(1) as f64;
+            let active = backend.active_connections.load(Ordering::Relaxed) as f64;
+            let score = active / weight;
+
+            if best.map_or(true, |(_, best_score)| score < best_score) {
+                best = Some((backend, score));
+            }
+        }
+
+        best.map(|(backend, _)| backend.backend.clone())
+    }
+
+    /// Fold a freshly observed connect/response latency sample into a
+    /// backend's Peak-EWMA, decaying the previous value by elapsed wall-clock
+    /// time (`PEAK_EWMA_TAU_SECS`) rather than a fixed per-sample weight, so a
+    /// backend that's been quiet responds to a fresh sample almost
+    /// immediately instead of dragging a stale average. Distinct from
+    /// `record_backend_latency`, which feeds `rtt_ewma`/`least_rtt`.
+    pub fn record_latency(&self, backend_addr: &str, rtt_ms: f64) {
+        let backends = self.backends.read();
+        let backend = match backends.iter().find(|b| b.backend.address == backend_addr) {
+            Some(backend) => backend,
+            None => return,
+        };
+
+        let now = Instant::now();
+        let dt_secs = {
+            let mut last_update = backend.ewma_last_update.lock();
+            let dt_secs = last_update.map_or(0.0, |prev| (now - prev).as_secs_f64());
+            *last_update = Some(now);
+            dt_secs
+        };
+
+        let mut current_bits = backend.ewma_rtt_ms.load(Ordering::Relaxed);
+        loop {
+            let current_ms = f64::from_bits(current_bits);
+            let updated_ms = if current_ms <= 0.0 {
+                rtt_ms
+            } else {
+                let decay = 1.0 - (-dt_secs / PEAK_EWMA_TAU_SECS).exp();
+                current_ms + decay * (rtt_ms - current_ms)
+            };
+            match backend.ewma_rtt_ms.compare_exchange_weak(
+                current_bits,
+                updated_ms.to_bits(),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(actual) => current_bits = actual,
+            }
+        }
     }
 
     /// Increment active connection count for a backend
@@ -191,9 +472,14 @@ impl LoadBalancer {
             .map(|b| BackendWithStats {
                 backend: b,
                 active_connections: AtomicU64::new(0),
+                ewma_rtt_ms: AtomicU64::new(0),
+                ewma_last_update: Mutex::new(None),
             })
             .collect();
         *self.backends.write() = backends_with_stats;
+        // Invalidate the consistent-hash ring; it's rebuilt lazily on the
+        // next selection that needs it.
+        self.backends_version.fetch_add(1, Ordering::Release);
     }
 
     /// Get connection statistics for monitoring
@@ -210,3 +496,19 @@ impl LoadBalancer {
             .collect()
     }
 }
+
+/// FNV-1a 64-bit hash. Used for the consistent-hash ring instead of
+/// `DefaultHasher`, whose output is not guaranteed stable across Rust
+/// versions - that instability would silently reshuffle the entire ring on
+/// a toolchain upgrade instead of only remapping on backend-set changes.
+fn stable_hash(s: &str) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+
+    let mut hash = FNV_OFFSET_BASIS;
+    for byte in s.as_bytes() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
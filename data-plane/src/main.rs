@@ -3,14 +3,22 @@ use tokio::signal;
 use tracing::{error, info};
 use tracing_subscriber;
 
+mod access_control;
 mod config;
 mod connection;
+#[cfg(feature = "redis-discovery")]
+mod discovery;
 mod grpc_server;
 mod load_balancer;
 mod metrics;
+mod privdrop;
+mod supervisor;
+mod tcp_info;
 mod tcp_proxy;
 mod udp_proxy;
 
+use load_balancer::LoadBalancer;
+
 use config::ProxyState;
 use grpc_server::ProxyControlService;
 
@@ -53,10 +61,47 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     info!("Configuration received, starting proxy services");
 
+    // Bind every privileged listening port up front, so we can drop
+    // privileges before a single connection or packet is processed.
+    let startup_config = proxy_state.get_config().ok_or("Proxy not configured")?;
+    let tcp_listener = tcp_proxy::bind(&startup_config).await?;
+    let udp_socket = udp_proxy::bind(&startup_config).await?;
+
+    privdrop::drop_privileges(&startup_config.privdrop)?;
+
+    // Shared between both proxies (and, if enabled, Redis discovery) so a
+    // backend-set update applied to it is visible to all of them without
+    // either listener restarting. Control-plane backend updates reach it
+    // through the UDP proxy's reconfiguration task; a control-plane change
+    // of algorithm makes the UDP proxy switch to a new load balancer of its
+    // own, which the TCP proxy and Redis discovery do not share.
+    let load_balancer = Arc::new(LoadBalancer::with_max_backend_rtt(
+        startup_config.backends.clone(),
+        startup_config.algorithm.clone(),
+        std::time::Duration::from_millis(startup_config.max_backend_rtt_ms),
+    ));
+
+    // Start Redis-backed backend discovery in place of the gRPC control
+    // plane's backend list, if configured.
+    #[cfg(feature = "redis-discovery")]
+    if startup_config.discovery_backend == "redis" {
+        if let Some(redis_config) = startup_config.redis_discovery.clone() {
+            let discovery_lb = load_balancer.clone();
+            tokio::spawn(async move {
+                if let Err(e) = discovery::run(redis_config, discovery_lb).await {
+                    error!("Redis discovery error: {}", e);
+                }
+            });
+        } else {
+            error!("discovery_backend is \"redis\" but no Redis discovery config was supplied");
+        }
+    }
+
     // Start TCP proxy
     let tcp_state = proxy_state.clone();
+    let tcp_load_balancer = load_balancer.clone();
     let tcp_handle = tokio::spawn(async move {
-        if let Err(e) = tcp_proxy::run(tcp_state).await {
+        if let Err(e) = tcp_proxy::run(tcp_listener, tcp_state, tcp_load_balancer).await {
             error!("TCP proxy error: {}", e);
         }
     });
@@ -64,7 +109,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Start UDP proxy
     let udp_state = proxy_state.clone();
     let udp_handle = tokio::spawn(async move {
-        if let Err(e) = udp_proxy::run(udp_state).await {
+        if let Err(e) = udp_proxy::run(udp_socket, udp_state, load_balancer).await {
             error!("UDP proxy error: {}", e);
         }
     });
@@ -75,6 +120,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         metrics::stream_metrics(metrics_state).await;
     });
 
+    // Start Prometheus scrape endpoint
+    let metrics_http_state = proxy_state.clone();
+    let metrics_bind_address = startup_config.metrics_bind_address.clone();
+    let metrics_http_handle = tokio::spawn(async move {
+        if let Err(e) = metrics::serve_metrics(metrics_http_state, &metrics_bind_address).await {
+            error!("Metrics endpoint error: {}", e);
+        }
+    });
+
     // Wait for shutdown signal
     info!("Proxy data plane ready");
     signal::ctrl_c().await?;
@@ -85,7 +139,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Wait for all tasks to complete (with timeout)
     let _ = tokio::time::timeout(tokio::time::Duration::from_secs(30), async {
-        let _ = tokio::join!(grpc_handle, tcp_handle, udp_handle, metrics_handle);
+        let _ = tokio::join!(
+            grpc_handle,
+            tcp_handle,
+            udp_handle,
+            metrics_handle,
+            metrics_http_handle
+        );
     })
     .await;
 
@@ -1,10 +1,12 @@
 use parking_lot::RwLock;
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use tracing::debug;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tracing::{debug, error, info};
 
-use crate::config::ProxyState;
+use crate::config::{BackendId, ProxyState};
 
 pub async fn stream_metrics(state: Arc<ProxyState>) {
     let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(5));
@@ -12,49 +14,279 @@ pub async fn stream_metrics(state: Arc<ProxyState>) {
     loop {
         interval.tick().await;
 
-        let active_connections = state.active_connection_count() as i64;
-        let metrics = state.get_metrics();
+        state.circuit_breaker().tick();
+
+        let active_connections = state.connection_supervisor().live_task_count() as i64;
+        let summary = state.get_metrics().get_summary();
 
         debug!(
             "Metrics: active_connections={}, total_tcp={}, total_udp={}, total_bytes_sent={}, total_bytes_received={}",
             active_connections,
-            metrics.tcp_connections.load(Ordering::Relaxed),
-            metrics.udp_sessions.load(Ordering::Relaxed),
-            metrics.bytes_sent.load(Ordering::Relaxed),
-            metrics.bytes_received.load(Ordering::Relaxed)
+            summary.tcp_connections,
+            summary.udp_sessions,
+            summary.bytes_sent,
+            summary.bytes_received
         );
+    }
+}
+
+/// Serve a Prometheus text-exposition scrape endpoint on `bind_addr`.
+///
+/// Handles one request per connection: reads and discards the request,
+/// then writes back the full metrics snapshot regardless of path, so any
+/// scraper hitting `/metrics` (or anything else) gets the same body.
+pub async fn serve_metrics(state: Arc<ProxyState>, bind_addr: &str) -> std::io::Result<()> {
+    let listener = TcpListener::bind(bind_addr).await?;
+    info!("Metrics endpoint listening on {}", bind_addr);
+
+    loop {
+        let (mut socket, _) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                error!("Failed to accept metrics connection: {}", e);
+                continue;
+            }
+        };
+
+        let state = state.clone();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            // We don't care about the request beyond draining it off the wire.
+            if socket.read(&mut buf).await.is_err() {
+                return;
+            }
+
+            let body = render_prometheus(&state);
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+
+            if let Err(e) = socket.write_all(response.as_bytes()).await {
+                debug!("Failed to write metrics response: {}", e);
+            }
+        });
+    }
+}
+
+/// Render the current proxy state as Prometheus text-exposition format.
+fn render_prometheus(state: &ProxyState) -> String {
+    let metrics = state.get_metrics();
+    let summary = metrics.get_summary();
+    let mut out = String::new();
+
+    out.push_str("# HELP aegis_tcp_connections_total Total TCP connections accepted.\n");
+    out.push_str("# TYPE aegis_tcp_connections_total counter\n");
+    out.push_str(&format!("aegis_tcp_connections_total {}\n", summary.tcp_connections));
+
+    out.push_str("# HELP aegis_udp_sessions_total Total UDP sessions established.\n");
+    out.push_str("# TYPE aegis_udp_sessions_total counter\n");
+    out.push_str(&format!("aegis_udp_sessions_total {}\n", summary.udp_sessions));
+
+    out.push_str("# HELP aegis_active_tcp_connections Currently open TCP connections.\n");
+    out.push_str("# TYPE aegis_active_tcp_connections gauge\n");
+    out.push_str(&format!(
+        "aegis_active_tcp_connections {}\n",
+        summary.active_tcp_connections
+    ));
+
+    out.push_str("# HELP aegis_active_udp_sessions Currently tracked UDP sessions.\n");
+    out.push_str("# TYPE aegis_active_udp_sessions gauge\n");
+    out.push_str(&format!(
+        "aegis_active_udp_sessions {}\n",
+        summary.active_udp_sessions
+    ));
+
+    out.push_str("# HELP aegis_bytes_sent_total Total bytes forwarded from clients to backends.\n");
+    out.push_str("# TYPE aegis_bytes_sent_total counter\n");
+    out.push_str(&format!("aegis_bytes_sent_total {}\n", summary.bytes_sent));
+
+    out.push_str("# HELP aegis_bytes_received_total Total bytes received from backends for clients.\n");
+    out.push_str("# TYPE aegis_bytes_received_total counter\n");
+    out.push_str(&format!("aegis_bytes_received_total {}\n", summary.bytes_received));
+
+    out.push_str("# HELP aegis_packets_sent_total Total UDP packets forwarded from clients to backends.\n");
+    out.push_str("# TYPE aegis_packets_sent_total counter\n");
+    out.push_str(&format!("aegis_packets_sent_total {}\n", summary.packets_sent));
+
+    out.push_str("# HELP aegis_packets_received_total Total UDP packets received from backends for clients.\n");
+    out.push_str("# TYPE aegis_packets_received_total counter\n");
+    out.push_str(&format!("aegis_packets_received_total {}\n", summary.packets_received));
+
+    out.push_str("# HELP aegis_rate_limit_allowed_total Requests allowed by the rate limiter.\n");
+    out.push_str("# TYPE aegis_rate_limit_allowed_total counter\n");
+    out.push_str(&format!(
+        "aegis_rate_limit_allowed_total {}\n",
+        summary.rate_limit_allowed
+    ));
+
+    out.push_str("# HELP aegis_rate_limit_denied_total Requests denied by the rate limiter.\n");
+    out.push_str("# TYPE aegis_rate_limit_denied_total counter\n");
+    out.push_str(&format!(
+        "aegis_rate_limit_denied_total {}\n",
+        summary.rate_limit_denied
+    ));
+
+    out.push_str("# HELP aegis_circuit_breaker_open_total Requests rejected by an open circuit breaker.\n");
+    out.push_str("# TYPE aegis_circuit_breaker_open_total counter\n");
+    out.push_str(&format!(
+        "aegis_circuit_breaker_open_total {}\n",
+        summary.circuit_breaker_open
+    ));
+
+    out.push_str("# HELP aegis_circuit_breaker_half_open_total Times a circuit breaker probed half-open.\n");
+    out.push_str("# TYPE aegis_circuit_breaker_half_open_total counter\n");
+    out.push_str(&format!(
+        "aegis_circuit_breaker_half_open_total {}\n",
+        summary.circuit_breaker_half_open
+    ));
+
+    out.push_str("# HELP aegis_replay_dropped_total UDP packets dropped by the anti-replay filter.\n");
+    out.push_str("# TYPE aegis_replay_dropped_total counter\n");
+    out.push_str(&format!("aegis_replay_dropped_total {}\n", summary.replay_dropped));
+
+    out.push_str("# HELP aegis_tcp_retransmits_total TCP segments retransmitted, sampled via TCP_INFO.\n");
+    out.push_str("# TYPE aegis_tcp_retransmits_total counter\n");
+    out.push_str(&format!("aegis_tcp_retransmits_total {}\n", summary.tcp_retransmits));
+
+    out.push_str("# HELP aegis_task_panics_total Supervised connection tasks that panicked.\n");
+    out.push_str("# TYPE aegis_task_panics_total counter\n");
+    out.push_str(&format!("aegis_task_panics_total {}\n", summary.task_panics));
+
+    out.push_str("# HELP aegis_backend_requests_total Requests forwarded per backend.\n");
+    out.push_str("# TYPE aegis_backend_requests_total counter\n");
+    for (addr, bm) in metrics.get_backend_metrics() {
+        out.push_str(&format!(
+            "aegis_backend_requests_total{{backend=\"{}\"}} {}\n",
+            addr,
+            bm.requests.load(Ordering::Relaxed)
+        ));
+    }
+
+    out.push_str("# HELP aegis_backend_failures_total Failed requests per backend.\n");
+    out.push_str("# TYPE aegis_backend_failures_total counter\n");
+    for (addr, bm) in metrics.get_backend_metrics() {
+        out.push_str(&format!(
+            "aegis_backend_failures_total{{backend=\"{}\"}} {}\n",
+            addr,
+            bm.failures.load(Ordering::Relaxed)
+        ));
+    }
+
+    out.push_str("# HELP aegis_backend_bytes_sent_total Bytes sent per backend.\n");
+    out.push_str("# TYPE aegis_backend_bytes_sent_total counter\n");
+    for (addr, bm) in metrics.get_backend_metrics() {
+        out.push_str(&format!(
+            "aegis_backend_bytes_sent_total{{backend=\"{}\"}} {}\n",
+            addr,
+            bm.bytes_sent.load(Ordering::Relaxed)
+        ));
+    }
 
-        // TODO: Send metrics via gRPC stream to control plane
+    out.push_str("# HELP aegis_backend_bytes_received_total Bytes received per backend.\n");
+    out.push_str("# TYPE aegis_backend_bytes_received_total counter\n");
+    for (addr, bm) in metrics.get_backend_metrics() {
+        out.push_str(&format!(
+            "aegis_backend_bytes_received_total{{backend=\"{}\"}} {}\n",
+            addr,
+            bm.bytes_received.load(Ordering::Relaxed)
+        ));
     }
+
+    let latency = &summary.latency;
+    out.push_str("# HELP aegis_latency_ms Backend connect time and TCP_INFO RTT quantiles in milliseconds.\n");
+    out.push_str("# TYPE aegis_latency_ms summary\n");
+    out.push_str(&format!("aegis_latency_ms{{quantile=\"0.5\"}} {}\n", latency.p50));
+    out.push_str(&format!("aegis_latency_ms{{quantile=\"0.9\"}} {}\n", latency.p90));
+    out.push_str(&format!("aegis_latency_ms{{quantile=\"0.99\"}} {}\n", latency.p99));
+
+    out
 }
 
-/// Comprehensive metrics collector for proxy operations
+/// Number of metrics shards to spread writes across. Sized generously rather
+/// than tied to `available_parallelism()` so the shard a task lands on stays
+/// stable even if the runtime's worker count changes at startup.
+const SHARD_COUNT: usize = 16;
+
+thread_local! {
+    /// Each OS thread (i.e. each tokio worker) is assigned a shard the first
+    /// time it touches the collector, and keeps using it for its lifetime.
+    static SHARD_INDEX: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed) % SHARD_COUNT;
+}
+
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+/// Comprehensive metrics collector for proxy operations.
+///
+/// Writes are sharded per worker thread so hot-path `record_*` calls from
+/// different threads don't contend with each other (beyond `SHARD_COUNT`
+/// threads, shards are shared round-robin); reads fold all shards together,
+/// which is fine since reads only happen on the 5s scrape/log cadence.
 pub struct MetricsCollector {
+    shards: Vec<MetricsShard>,
+}
+
+/// A single un-contended shard of the counters below.
+struct MetricsShard {
     // Connection metrics
-    pub tcp_connections: AtomicU64,
-    pub udp_sessions: AtomicU64,
-    pub active_tcp_connections: AtomicU64,
-    pub active_udp_sessions: AtomicU64,
-    
+    tcp_connections: AtomicU64,
+    udp_sessions: AtomicU64,
+    active_tcp_connections: AtomicU64,
+    active_udp_sessions: AtomicU64,
+
     // Bandwidth metrics
-    pub bytes_sent: AtomicU64,
-    pub bytes_received: AtomicU64,
-    pub packets_sent: AtomicU64,
-    pub packets_received: AtomicU64,
-    
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    packets_sent: AtomicU64,
+    packets_received: AtomicU64,
+
     // Performance metrics
     latency_samples: RwLock<Vec<f64>>,
-    
+
     // Per-backend metrics
-    backend_metrics: RwLock<HashMap<String, BackendMetrics>>,
-    
+    backend_metrics: RwLock<HashMap<BackendId, BackendMetrics>>,
+
     // Rate limiting metrics
-    pub rate_limit_allowed: AtomicU64,
-    pub rate_limit_denied: AtomicU64,
-    
+    rate_limit_allowed: AtomicU64,
+    rate_limit_denied: AtomicU64,
+
     // Circuit breaker metrics
-    pub circuit_breaker_open: AtomicU64,
-    pub circuit_breaker_half_open: AtomicU64,
+    circuit_breaker_open: AtomicU64,
+    circuit_breaker_half_open: AtomicU64,
+
+    // UDP anti-replay metrics
+    replay_dropped: AtomicU64,
+
+    // Transport-level health, sampled from TCP_INFO
+    tcp_retransmits: AtomicU64,
+
+    // Connection supervisor metrics
+    task_panics: AtomicU64,
+}
+
+impl MetricsShard {
+    fn new() -> Self {
+        Self {
+            tcp_connections: AtomicU64::new(0),
+            udp_sessions: AtomicU64::new(0),
+            active_tcp_connections: AtomicU64::new(0),
+            active_udp_sessions: AtomicU64::new(0),
+            bytes_sent: AtomicU64::new(0),
+            bytes_received: AtomicU64::new(0),
+            packets_sent: AtomicU64::new(0),
+            packets_received: AtomicU64::new(0),
+            latency_samples: RwLock::new(Vec::new()),
+            backend_metrics: RwLock::new(HashMap::new()),
+            rate_limit_allowed: AtomicU64::new(0),
+            rate_limit_denied: AtomicU64::new(0),
+            circuit_breaker_open: AtomicU64::new(0),
+            circuit_breaker_half_open: AtomicU64::new(0),
+            replay_dropped: AtomicU64::new(0),
+            tcp_retransmits: AtomicU64::new(0),
+            task_panics: AtomicU64::new(0),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -93,66 +325,61 @@ impl BackendMetrics {
 impl MetricsCollector {
     pub fn new() -> Self {
         Self {
-            tcp_connections: AtomicU64::new(0),
-            udp_sessions: AtomicU64::new(0),
-            active_tcp_connections: AtomicU64::new(0),
-            active_udp_sessions: AtomicU64::new(0),
-            bytes_sent: AtomicU64::new(0),
-            bytes_received: AtomicU64::new(0),
-            packets_sent: AtomicU64::new(0),
-            packets_received: AtomicU64::new(0),
-            latency_samples: RwLock::new(Vec::new()),
-            backend_metrics: RwLock::new(HashMap::new()),
-            rate_limit_allowed: AtomicU64::new(0),
-            rate_limit_denied: AtomicU64::new(0),
-            circuit_breaker_open: AtomicU64::new(0),
-            circuit_breaker_half_open: AtomicU64::new(0),
+            shards: (0..SHARD_COUNT).map(|_| MetricsShard::new()).collect(),
         }
     }
 
+    /// The shard for the calling thread. Stable for the thread's lifetime.
+    fn shard(&self) -> &MetricsShard {
+        let idx = SHARD_INDEX.with(|idx| *idx);
+        &self.shards[idx]
+    }
+
     // TCP Connection metrics
     pub fn record_tcp_connection(&self) {
-        self.tcp_connections.fetch_add(1, Ordering::Relaxed);
-        self.active_tcp_connections.fetch_add(1, Ordering::Relaxed);
+        let shard = self.shard();
+        shard.tcp_connections.fetch_add(1, Ordering::Relaxed);
+        shard.active_tcp_connections.fetch_add(1, Ordering::Relaxed);
     }
 
     pub fn close_tcp_connection(&self) {
-        self.active_tcp_connections.fetch_sub(1, Ordering::Relaxed);
+        self.shard().active_tcp_connections.fetch_sub(1, Ordering::Relaxed);
     }
 
     // UDP Session metrics
     pub fn record_udp_session(&self) {
-        self.udp_sessions.fetch_add(1, Ordering::Relaxed);
-        self.active_udp_sessions.fetch_add(1, Ordering::Relaxed);
+        let shard = self.shard();
+        shard.udp_sessions.fetch_add(1, Ordering::Relaxed);
+        shard.active_udp_sessions.fetch_add(1, Ordering::Relaxed);
     }
 
     pub fn close_udp_session(&self) {
-        self.active_udp_sessions.fetch_sub(1, Ordering::Relaxed);
+        self.shard().active_udp_sessions.fetch_sub(1, Ordering::Relaxed);
     }
 
     // Bandwidth metrics
     pub fn record_bytes_sent(&self, bytes: u64) {
-        self.bytes_sent.fetch_add(bytes, Ordering::Relaxed);
+        self.shard().bytes_sent.fetch_add(bytes, Ordering::Relaxed);
     }
 
     pub fn record_bytes_received(&self, bytes: u64) {
-        self.bytes_received.fetch_add(bytes, Ordering::Relaxed);
+        self.shard().bytes_received.fetch_add(bytes, Ordering::Relaxed);
     }
 
     pub fn record_packet_sent(&self) {
-        self.packets_sent.fetch_add(1, Ordering::Relaxed);
+        self.shard().packets_sent.fetch_add(1, Ordering::Relaxed);
     }
 
     pub fn record_packet_received(&self) {
-        self.packets_received.fetch_add(1, Ordering::Relaxed);
+        self.shard().packets_received.fetch_add(1, Ordering::Relaxed);
     }
 
     // Latency tracking
     pub fn record_latency(&self, duration_ms: f64) {
-        let mut samples = self.latency_samples.write();
+        let mut samples = self.shard().latency_samples.write();
         samples.push(duration_ms);
-        
-        // Keep only last 1000 samples
+
+        // Keep only last 1000 samples per shard
         let len = samples.len();
         if len > 1000 {
             samples.drain(0..(len - 1000));
@@ -160,109 +387,279 @@ impl MetricsCollector {
     }
 
     pub fn get_latency_stats(&self) -> LatencyStats {
-        let samples = self.latency_samples.read();
-        
-        if samples.is_empty() {
+        let mut all_samples = Vec::new();
+        for shard in &self.shards {
+            all_samples.extend(shard.latency_samples.read().iter().copied());
+        }
+
+        if all_samples.is_empty() {
             return LatencyStats::default();
         }
 
-        let mut sorted = samples.clone();
-        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        all_samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
 
-        let len = sorted.len();
-        let p50 = sorted[len / 2];
-        let p90 = sorted[(len * 90) / 100];
-        let p99 = sorted[(len * 99) / 100];
-        let avg = sorted.iter().sum::<f64>() / len as f64;
+        let len = all_samples.len();
+        let p50 = all_samples[len / 2];
+        let p90 = all_samples[(len * 90) / 100];
+        let p99 = all_samples[(len * 99) / 100];
+        let avg = all_samples.iter().sum::<f64>() / len as f64;
 
         LatencyStats { p50, p90, p99, avg }
     }
 
     // Backend metrics
-    pub fn record_backend_request(&self, backend: &str) {
-        let mut backends = self.backend_metrics.write();
+    pub fn record_backend_request(&self, backend: &BackendId) {
+        let mut backends = self.shard().backend_metrics.write();
         backends
-            .entry(backend.to_string())
+            .entry(backend.clone())
             .or_insert_with(BackendMetrics::new)
             .requests
             .fetch_add(1, Ordering::Relaxed);
     }
 
-    pub fn record_backend_connection(&self, backend: &str) {
-        let mut backends = self.backend_metrics.write();
+    pub fn record_backend_connection(&self, backend: &BackendId) {
+        let mut backends = self.shard().backend_metrics.write();
         backends
-            .entry(backend.to_string())
+            .entry(backend.clone())
             .or_insert_with(BackendMetrics::new)
             .connections
             .fetch_add(1, Ordering::Relaxed);
     }
 
-    pub fn record_backend_failure(&self, backend: &str) {
-        let mut backends = self.backend_metrics.write();
+    pub fn record_backend_failure(&self, backend: &BackendId) {
+        let mut backends = self.shard().backend_metrics.write();
         backends
-            .entry(backend.to_string())
+            .entry(backend.clone())
             .or_insert_with(BackendMetrics::new)
             .failures
             .fetch_add(1, Ordering::Relaxed);
     }
 
-    pub fn record_backend_bytes_sent(&self, backend: &str, bytes: u64) {
-        let mut backends = self.backend_metrics.write();
+    pub fn record_backend_bytes_sent(&self, backend: &BackendId, bytes: u64) {
+        let mut backends = self.shard().backend_metrics.write();
         backends
-            .entry(backend.to_string())
+            .entry(backend.clone())
             .or_insert_with(BackendMetrics::new)
             .bytes_sent
             .fetch_add(bytes, Ordering::Relaxed);
     }
 
-    pub fn record_backend_bytes_received(&self, backend: &str, bytes: u64) {
-        let mut backends = self.backend_metrics.write();
+    pub fn record_backend_bytes_received(&self, backend: &BackendId, bytes: u64) {
+        let mut backends = self.shard().backend_metrics.write();
         backends
-            .entry(backend.to_string())
+            .entry(backend.clone())
             .or_insert_with(BackendMetrics::new)
             .bytes_received
             .fetch_add(bytes, Ordering::Relaxed);
     }
 
-    pub fn get_backend_metrics(&self) -> HashMap<String, BackendMetrics> {
-        self.backend_metrics.read().clone()
+    /// Fold per-shard backend maps into a single combined view.
+    pub fn get_backend_metrics(&self) -> HashMap<BackendId, BackendMetrics> {
+        let mut combined: HashMap<BackendId, BackendMetrics> = HashMap::new();
+
+        for shard in &self.shards {
+            for (addr, bm) in shard.backend_metrics.read().iter() {
+                let entry = combined.entry(addr.clone()).or_insert_with(BackendMetrics::new);
+                entry.connections.fetch_add(bm.connections.load(Ordering::Relaxed), Ordering::Relaxed);
+                entry.requests.fetch_add(bm.requests.load(Ordering::Relaxed), Ordering::Relaxed);
+                entry.failures.fetch_add(bm.failures.load(Ordering::Relaxed), Ordering::Relaxed);
+                entry.bytes_sent.fetch_add(bm.bytes_sent.load(Ordering::Relaxed), Ordering::Relaxed);
+                entry.bytes_received.fetch_add(bm.bytes_received.load(Ordering::Relaxed), Ordering::Relaxed);
+            }
+        }
+
+        combined
     }
 
     // Rate limiting metrics
     pub fn record_rate_limit_allowed(&self) {
-        self.rate_limit_allowed.fetch_add(1, Ordering::Relaxed);
+        self.shard().rate_limit_allowed.fetch_add(1, Ordering::Relaxed);
     }
 
     pub fn record_rate_limit_denied(&self) {
-        self.rate_limit_denied.fetch_add(1, Ordering::Relaxed);
+        self.shard().rate_limit_denied.fetch_add(1, Ordering::Relaxed);
     }
 
     // Circuit breaker metrics
     pub fn record_circuit_breaker_open(&self) {
-        self.circuit_breaker_open.fetch_add(1, Ordering::Relaxed);
+        self.shard().circuit_breaker_open.fetch_add(1, Ordering::Relaxed);
     }
 
     pub fn record_circuit_breaker_half_open(&self) {
-        self.circuit_breaker_half_open.fetch_add(1, Ordering::Relaxed);
+        self.shard().circuit_breaker_half_open.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // UDP anti-replay metrics
+    pub fn record_replay_dropped(&self) {
+        self.shard().replay_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Transport-level health
+    pub fn record_tcp_retransmits(&self, count: u64) {
+        self.shard().tcp_retransmits.fetch_add(count, Ordering::Relaxed);
+    }
+
+    // Connection supervisor metrics
+    pub fn record_task_panic(&self) {
+        self.shard().task_panics.fetch_add(1, Ordering::Relaxed);
     }
 
-    // Get summary for logging/monitoring
+    /// Fold a telemetry sample reported by a remote node over the gRPC
+    /// control-plane `stream_metrics` RPC into this node's own registry, so
+    /// a scrape of this node's `/metrics` reflects the whole fleet rather
+    /// than just local traffic. Field names mirror the counters this node
+    /// already exports.
+    ///
+    /// Every field is a cumulative running total on the wire, same as this
+    /// node's own counters, except the two active-connection gauges - so
+    /// `previous` (the peer's prior sample on the same stream, if any) is
+    /// used to fold in only the delta since the peer's last report, the
+    /// same way `tcp_proxy` diffs the cumulative `TCP_INFO` retransmit
+    /// counter rather than re-adding the running total every tick. The
+    /// gauges are folded as a signed delta against the peer's previous
+    /// report so a peer's live connections are added once and removed again
+    /// as they close, instead of accumulating forever.
+    pub fn record_external_sample(
+        &self,
+        sample: &crate::config::proxy::MetricsData,
+        previous: Option<&crate::config::proxy::MetricsData>,
+    ) {
+        let shard = self.shard();
+
+        let prev_tcp_connections = previous.map_or(0, |p| p.tcp_connections);
+        shard.tcp_connections.fetch_add(
+            sample.tcp_connections.saturating_sub(prev_tcp_connections),
+            Ordering::Relaxed,
+        );
+
+        let prev_udp_sessions = previous.map_or(0, |p| p.udp_sessions);
+        shard.udp_sessions.fetch_add(
+            sample.udp_sessions.saturating_sub(prev_udp_sessions),
+            Ordering::Relaxed,
+        );
+
+        let prev_active_tcp = previous.map_or(0, |p| p.active_tcp_connections) as i64;
+        let active_tcp_delta = sample.active_tcp_connections as i64 - prev_active_tcp;
+        if active_tcp_delta >= 0 {
+            shard.active_tcp_connections.fetch_add(active_tcp_delta as u64, Ordering::Relaxed);
+        } else {
+            shard.active_tcp_connections.fetch_sub((-active_tcp_delta) as u64, Ordering::Relaxed);
+        }
+
+        let prev_active_udp = previous.map_or(0, |p| p.active_udp_sessions) as i64;
+        let active_udp_delta = sample.active_udp_sessions as i64 - prev_active_udp;
+        if active_udp_delta >= 0 {
+            shard.active_udp_sessions.fetch_add(active_udp_delta as u64, Ordering::Relaxed);
+        } else {
+            shard.active_udp_sessions.fetch_sub((-active_udp_delta) as u64, Ordering::Relaxed);
+        }
+
+        let prev_bytes_sent = previous.map_or(0, |p| p.bytes_sent);
+        shard.bytes_sent.fetch_add(sample.bytes_sent.saturating_sub(prev_bytes_sent), Ordering::Relaxed);
+
+        let prev_bytes_received = previous.map_or(0, |p| p.bytes_received);
+        shard.bytes_received.fetch_add(
+            sample.bytes_received.saturating_sub(prev_bytes_received),
+            Ordering::Relaxed,
+        );
+
+        let prev_packets_sent = previous.map_or(0, |p| p.packets_sent);
+        shard.packets_sent.fetch_add(
+            sample.packets_sent.saturating_sub(prev_packets_sent),
+            Ordering::Relaxed,
+        );
+
+        let prev_packets_received = previous.map_or(0, |p| p.packets_received);
+        shard.packets_received.fetch_add(
+            sample.packets_received.saturating_sub(prev_packets_received),
+            Ordering::Relaxed,
+        );
+
+        let prev_rate_limit_allowed = previous.map_or(0, |p| p.rate_limit_allowed);
+        shard.rate_limit_allowed.fetch_add(
+            sample.rate_limit_allowed.saturating_sub(prev_rate_limit_allowed),
+            Ordering::Relaxed,
+        );
+
+        let prev_rate_limit_denied = previous.map_or(0, |p| p.rate_limit_denied);
+        shard.rate_limit_denied.fetch_add(
+            sample.rate_limit_denied.saturating_sub(prev_rate_limit_denied),
+            Ordering::Relaxed,
+        );
+
+        let prev_circuit_breaker_open = previous.map_or(0, |p| p.circuit_breaker_open);
+        shard.circuit_breaker_open.fetch_add(
+            sample.circuit_breaker_open.saturating_sub(prev_circuit_breaker_open),
+            Ordering::Relaxed,
+        );
+
+        let prev_circuit_breaker_half_open = previous.map_or(0, |p| p.circuit_breaker_half_open);
+        shard.circuit_breaker_half_open.fetch_add(
+            sample
+                .circuit_breaker_half_open
+                .saturating_sub(prev_circuit_breaker_half_open),
+            Ordering::Relaxed,
+        );
+
+        let prev_replay_dropped = previous.map_or(0, |p| p.replay_dropped);
+        shard.replay_dropped.fetch_add(
+            sample.replay_dropped.saturating_sub(prev_replay_dropped),
+            Ordering::Relaxed,
+        );
+
+        let prev_tcp_retransmits = previous.map_or(0, |p| p.tcp_retransmits);
+        shard.tcp_retransmits.fetch_add(
+            sample.tcp_retransmits.saturating_sub(prev_tcp_retransmits),
+            Ordering::Relaxed,
+        );
+
+        let prev_task_panics = previous.map_or(0, |p| p.task_panics);
+        shard.task_panics.fetch_add(
+            sample.task_panics.saturating_sub(prev_task_panics),
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Fold every shard's counters into one summary for logging/scraping.
     pub fn get_summary(&self) -> MetricsSummary {
-        MetricsSummary {
-            tcp_connections: self.tcp_connections.load(Ordering::Relaxed),
-            udp_sessions: self.udp_sessions.load(Ordering::Relaxed),
-            active_tcp_connections: self.active_tcp_connections.load(Ordering::Relaxed),
-            active_udp_sessions: self.active_udp_sessions.load(Ordering::Relaxed),
-            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
-            bytes_received: self.bytes_received.load(Ordering::Relaxed),
-            packets_sent: self.packets_sent.load(Ordering::Relaxed),
-            packets_received: self.packets_received.load(Ordering::Relaxed),
-            rate_limit_allowed: self.rate_limit_allowed.load(Ordering::Relaxed),
-            rate_limit_denied: self.rate_limit_denied.load(Ordering::Relaxed),
-            circuit_breaker_open: self.circuit_breaker_open.load(Ordering::Relaxed),
-            circuit_breaker_half_open: self.circuit_breaker_half_open.load(Ordering::Relaxed),
+        let mut summary = MetricsSummary {
+            tcp_connections: 0,
+            udp_sessions: 0,
+            active_tcp_connections: 0,
+            active_udp_sessions: 0,
+            bytes_sent: 0,
+            bytes_received: 0,
+            packets_sent: 0,
+            packets_received: 0,
+            rate_limit_allowed: 0,
+            rate_limit_denied: 0,
+            circuit_breaker_open: 0,
+            circuit_breaker_half_open: 0,
+            replay_dropped: 0,
+            tcp_retransmits: 0,
+            task_panics: 0,
             latency: self.get_latency_stats(),
+        };
+
+        for shard in &self.shards {
+            summary.tcp_connections += shard.tcp_connections.load(Ordering::Relaxed);
+            summary.udp_sessions += shard.udp_sessions.load(Ordering::Relaxed);
+            summary.active_tcp_connections += shard.active_tcp_connections.load(Ordering::Relaxed);
+            summary.active_udp_sessions += shard.active_udp_sessions.load(Ordering::Relaxed);
+            summary.bytes_sent += shard.bytes_sent.load(Ordering::Relaxed);
+            summary.bytes_received += shard.bytes_received.load(Ordering::Relaxed);
+            summary.packets_sent += shard.packets_sent.load(Ordering::Relaxed);
+            summary.packets_received += shard.packets_received.load(Ordering::Relaxed);
+            summary.rate_limit_allowed += shard.rate_limit_allowed.load(Ordering::Relaxed);
+            summary.rate_limit_denied += shard.rate_limit_denied.load(Ordering::Relaxed);
+            summary.circuit_breaker_open += shard.circuit_breaker_open.load(Ordering::Relaxed);
+            summary.circuit_breaker_half_open += shard.circuit_breaker_half_open.load(Ordering::Relaxed);
+            summary.replay_dropped += shard.replay_dropped.load(Ordering::Relaxed);
+            summary.tcp_retransmits += shard.tcp_retransmits.load(Ordering::Relaxed);
+            summary.task_panics += shard.task_panics.load(Ordering::Relaxed);
         }
+
+        summary
     }
 }
 
@@ -305,5 +702,8 @@ pub struct MetricsSummary {
     pub rate_limit_denied: u64,
     pub circuit_breaker_open: u64,
     pub circuit_breaker_half_open: u64,
+    pub replay_dropped: u64,
+    pub tcp_retransmits: u64,
+    pub task_panics: u64,
     pub latency: LatencyStats,
 }
@@ -0,0 +1,166 @@
+use std::ffi::CString;
+use std::io;
+
+/// Settings for the post-bind privilege drop. `user` is required for any
+/// drop to happen; `group` defaults to the user's primary group and
+/// `chroot_dir` is applied before the uid/gid switch if set.
+#[derive(Debug, Clone, Default)]
+pub struct PrivDropConfig {
+    pub user: Option<String>,
+    pub group: Option<String>,
+    pub chroot_dir: Option<String>,
+}
+
+impl PrivDropConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.user.is_some()
+    }
+}
+
+/// Drop from root to an unprivileged user once every privileged socket is
+/// bound, so a later remote-code-execution bug can't leverage root.
+///
+/// Order matters: supplementary groups are resolved via `initgroups` before
+/// `chroot`, since that lookup goes through NSS (`/etc/group` or nsswitch
+/// modules) which is typically unavailable once confined to a minimal
+/// chroot jail. `chroot` then runs before the primary gid, and the gid
+/// before the uid, because dropping the uid first would leave the process
+/// without permission to change its gid. After dropping, we verify the
+/// change can't be undone by attempting to reclaim root.
+#[cfg(unix)]
+pub fn drop_privileges(config: &PrivDropConfig) -> io::Result<()> {
+    let Some(user) = config.user.as_deref() else {
+        return Ok(());
+    };
+
+    let pw = lookup_user(user)?;
+    let gid = match config.group.as_deref() {
+        Some(group) => lookup_group(group)?,
+        None => pw.gid,
+    };
+
+    let c_user = CString::new(user)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "user name contains a NUL byte"))?;
+
+    // Supplementary groups first, from the target user's /etc/group entries,
+    // and before chroot() since initgroups() needs NSS access that a chroot
+    // jail usually doesn't provide.
+    if unsafe { libc::initgroups(c_user.as_ptr(), gid) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    if let Some(dir) = config.chroot_dir.as_deref() {
+        chroot(dir)?;
+    }
+
+    if unsafe { libc::setgid(gid) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    if unsafe { libc::setuid(pw.uid) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // The drop must be irreversible: if we can still reclaim root, the
+    // above setuid(2) did not leave root (e.g. the configured user is
+    // itself uid 0) and something about the deployment is misconfigured.
+    if unsafe { libc::setuid(0) } == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "privilege drop did not stick: still able to reclaim root",
+        ));
+    }
+
+    tracing::info!(
+        "Dropped privileges to user={} uid={} gid={}",
+        user,
+        pw.uid,
+        gid
+    );
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn drop_privileges(_config: &PrivDropConfig) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+struct PasswdEntry {
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+}
+
+#[cfg(unix)]
+fn lookup_user(name: &str) -> io::Result<PasswdEntry> {
+    let c_name = CString::new(name)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "user name contains a NUL byte"))?;
+
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0i8; 16384];
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+
+    let rc = unsafe {
+        libc::getpwnam_r(
+            c_name.as_ptr(),
+            &mut pwd,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+
+    if rc != 0 || result.is_null() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no such user: {}", name),
+        ));
+    }
+
+    Ok(PasswdEntry {
+        uid: pwd.pw_uid,
+        gid: pwd.pw_gid,
+    })
+}
+
+#[cfg(unix)]
+fn lookup_group(name: &str) -> io::Result<libc::gid_t> {
+    let c_name = CString::new(name)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "group name contains a NUL byte"))?;
+
+    let mut grp: libc::group = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0i8; 16384];
+    let mut result: *mut libc::group = std::ptr::null_mut();
+
+    let rc = unsafe {
+        libc::getgrnam_r(
+            c_name.as_ptr(),
+            &mut grp,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+
+    if rc != 0 || result.is_null() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no such group: {}", name),
+        ));
+    }
+
+    Ok(grp.gr_gid)
+}
+
+#[cfg(unix)]
+fn chroot(dir: &str) -> io::Result<()> {
+    let c_dir = CString::new(dir)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "chroot path contains a NUL byte"))?;
+
+    if unsafe { libc::chroot(c_dir.as_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    std::env::set_current_dir("/")
+}
@@ -1,7 +1,11 @@
-use parking_lot::Mutex;
+use parking_lot::{Mutex, RwLock};
 use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+use crate::access_control::AccessControl;
+
 /// Token bucket rate limiter implementation
 /// Provides both global and per-connection rate limiting
 pub struct TokenBucket {
@@ -56,6 +60,7 @@ pub struct RateLimiter {
     per_connection_limit: Option<(u64, u64)>, // (rps, burst)
     cleanup_interval: Duration,
     last_cleanup: Mutex<Instant>,
+    access_control: Option<Arc<AccessControl>>,
 }
 
 impl RateLimiter {
@@ -66,6 +71,7 @@ impl RateLimiter {
             per_connection_limit: None,
             cleanup_interval: Duration::from_secs(60),
             last_cleanup: Mutex::new(Instant::now()),
+            access_control: None,
         }
     }
 
@@ -74,8 +80,26 @@ impl RateLimiter {
         self
     }
 
-    /// Check if request should be allowed (global + per-connection limits)
+    /// Consult an [`AccessControl`] list before consuming any tokens, so a
+    /// denied source is rejected without touching the bucket state.
+    pub fn with_access_control(mut self, access_control: Arc<AccessControl>) -> Self {
+        self.access_control = Some(access_control);
+        self
+    }
+
+    /// Check if request should be allowed (ACL + global + per-connection limits)
     pub fn allow_request(&self, connection_id: Option<&str>) -> bool {
+        // Short-circuit denied sources before touching any bucket state.
+        // `connection_id` is typically a "ip:port" socket address string;
+        // anything else (e.g. an opaque test label) just skips the check.
+        if let (Some(acl), Some(conn_id)) = (&self.access_control, connection_id) {
+            if let Some(ip) = parse_connection_ip(conn_id) {
+                if !acl.check(ip) {
+                    return false;
+                }
+            }
+        }
+
         // Check global limit first
         if !self.global_limiter.lock().try_consume(1) {
             return false;
@@ -125,6 +149,312 @@ impl RateLimiter {
     }
 }
 
+/// Recover the source IP from a `connection_id`, which is a "ip:port"
+/// socket address string in every real call site. A bare IP address is
+/// accepted as well; returns `None` for anything else rather than failing
+/// the check.
+fn parse_connection_ip(conn_id: &str) -> Option<IpAddr> {
+    conn_id
+        .parse::<std::net::SocketAddr>()
+        .map(|addr| addr.ip())
+        .ok()
+        .or_else(|| conn_id.parse::<IpAddr>().ok())
+}
+
+/// A single source IP's token bucket.
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+/// Per-source-IP token-bucket limiter with load-shedding.
+///
+/// Unlike [`RateLimiter`], which keys on an opaque connection id, this keys
+/// strictly on the peer's IP so a spoofing or abusive source can't evade the
+/// limit by rotating ports. The bucket table is capped: once full, unknown
+/// sources are shed outright rather than growing the map, so an attacker
+/// spraying distinct source IPs can't exhaust memory.
+pub struct SourceRateLimiter {
+    buckets: RwLock<HashMap<IpAddr, Bucket>>,
+    rate: f64,
+    burst: f64,
+    max_entries: usize,
+    ttl: Duration,
+    cleanup_interval: Duration,
+    last_cleanup: Mutex<Instant>,
+}
+
+impl SourceRateLimiter {
+    pub fn new(rate: f64, burst: f64, max_entries: usize, ttl: Duration) -> Self {
+        Self {
+            buckets: RwLock::new(HashMap::new()),
+            rate,
+            burst,
+            max_entries,
+            ttl,
+            cleanup_interval: Duration::from_secs(30),
+            last_cleanup: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Check and consume a token for `ip`, allowing or denying the request.
+    pub fn allow(&self, ip: IpAddr) -> bool {
+        self.gc_if_due();
+
+        let now = Instant::now();
+        let mut buckets = self.buckets.write();
+
+        if let Some(bucket) = buckets.get_mut(&ip) {
+            let elapsed = now.duration_since(bucket.last).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+            bucket.last = now;
+
+            if bucket.tokens >= 1.0 {
+                bucket.tokens -= 1.0;
+                true
+            } else {
+                false
+            }
+        } else {
+            if buckets.len() >= self.max_entries {
+                // Table is full: shed new/unknown sources rather than grow.
+                return false;
+            }
+
+            buckets.insert(
+                ip,
+                Bucket {
+                    tokens: self.burst - 1.0,
+                    last: now,
+                },
+            );
+            true
+        }
+    }
+
+    /// Evict buckets that have been idle past the configured TTL, if the
+    /// cleanup interval has elapsed since the last pass.
+    fn gc_if_due(&self) {
+        let mut last_cleanup = self.last_cleanup.lock();
+        if last_cleanup.elapsed() < self.cleanup_interval {
+            return;
+        }
+
+        let now = Instant::now();
+        self.buckets
+            .write()
+            .retain(|_, bucket| now.duration_since(bucket.last) < self.ttl);
+
+        *last_cleanup = now;
+    }
+
+    /// Number of source IPs currently tracked.
+    pub fn tracked_sources(&self) -> usize {
+        self.buckets.read().len()
+    }
+}
+
+/// Key-extraction strategy for [`GcraLimiter`]. `Subnet` lets operators
+/// throttle a whole abusive block rather than individual ephemeral flows
+/// within it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GcraKeyStrategy {
+    /// Key on the exact address.
+    Exact,
+    /// Key on the containing IPv4 /24 or IPv6 /64.
+    Subnet,
+}
+
+impl GcraKeyStrategy {
+    fn apply(&self, ip: IpAddr) -> IpAddr {
+        match (self, ip) {
+            (GcraKeyStrategy::Exact, ip) => ip,
+            (GcraKeyStrategy::Subnet, IpAddr::V4(v4)) => {
+                let [a, b, c, _] = v4.octets();
+                IpAddr::V4(Ipv4Addr::new(a, b, c, 0))
+            }
+            (GcraKeyStrategy::Subnet, IpAddr::V6(v6)) => {
+                let mut segments = v6.segments();
+                segments[4] = 0;
+                segments[5] = 0;
+                segments[6] = 0;
+                segments[7] = 0;
+                IpAddr::V6(Ipv6Addr::from(segments))
+            }
+        }
+    }
+}
+
+/// GCRA (Generic Cell Rate Algorithm) rate limiter.
+///
+/// Unlike [`TokenBucket`], which stores a float balance refilled on every
+/// check, GCRA keeps a single "theoretical arrival time" (TAT) per key: on a
+/// request at `now`, with emission interval `t = 1/rps` and burst tolerance
+/// `tau = (burst - 1) * t`, the request is rejected if `now < tat - tau`, otherwise
+/// `tat` advances to `max(tat, now) + t` and the request is accepted. This is
+/// O(1) state per key and smoother than token refill, since it spaces
+/// accepted requests out by `t` rather than letting a full bucket through in
+/// a burst. Keying can aggregate by subnet so a source can't dodge the limit
+/// by rotating through many addresses in the same block.
+pub struct GcraLimiter {
+    arrivals: RwLock<HashMap<IpAddr, Instant>>,
+    emission_interval: Duration,
+    burst_tolerance: Duration,
+    key_strategy: GcraKeyStrategy,
+    max_entries: usize,
+    ttl: Duration,
+    cleanup_interval: Duration,
+    last_cleanup: Mutex<Instant>,
+}
+
+impl GcraLimiter {
+    pub fn new(
+        requests_per_second: f64,
+        burst: u32,
+        key_strategy: GcraKeyStrategy,
+        max_entries: usize,
+        ttl: Duration,
+    ) -> Self {
+        let emission_interval = Duration::from_secs_f64(1.0 / requests_per_second);
+        Self {
+            arrivals: RwLock::new(HashMap::new()),
+            emission_interval,
+            // `burst` immediate requests should be admitted, not `burst + 1`:
+            // the first request already consumes one emission interval via
+            // the `tat.max(now) + emission_interval` advance below, so the
+            // tolerance only needs to cover the remaining `burst - 1`.
+            burst_tolerance: emission_interval * burst.saturating_sub(1),
+            key_strategy,
+            max_entries,
+            ttl,
+            cleanup_interval: Duration::from_secs(30),
+            last_cleanup: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Check and advance the TAT for `ip`'s key, allowing or denying the request.
+    pub fn allow(&self, ip: IpAddr) -> bool {
+        self.gc_if_due();
+
+        let key = self.key_strategy.apply(ip);
+        let now = Instant::now();
+        let mut arrivals = self.arrivals.write();
+
+        match arrivals.get(&key).copied() {
+            Some(tat) => {
+                // Equivalent to `now < tat - tau`, written as an addition so
+                // it can't underflow if `tat` is close to the process epoch.
+                if now + self.burst_tolerance < tat {
+                    false
+                } else {
+                    arrivals.insert(key, tat.max(now) + self.emission_interval);
+                    true
+                }
+            }
+            None => {
+                if arrivals.len() >= self.max_entries {
+                    // Table is full: shed new/unknown keys rather than grow.
+                    return false;
+                }
+                arrivals.insert(key, now + self.emission_interval);
+                true
+            }
+        }
+    }
+
+    /// Evict keys whose TAT has fallen more than the TTL behind the current
+    /// time (i.e. idle long enough that they've "caught up"), if the cleanup
+    /// interval has elapsed since the last pass.
+    fn gc_if_due(&self) {
+        let mut last_cleanup = self.last_cleanup.lock();
+        if last_cleanup.elapsed() < self.cleanup_interval {
+            return;
+        }
+
+        let now = Instant::now();
+        let ttl = self.ttl;
+        self.arrivals.write().retain(|_, tat| *tat + ttl > now);
+
+        *last_cleanup = now;
+    }
+
+    /// Number of keys currently tracked.
+    pub fn tracked_keys(&self) -> usize {
+        self.arrivals.read().len()
+    }
+}
+
+/// Selectable per-source-IP limiting strategy for the accept path, pushed by
+/// the control plane as `ProxyConfig::rate_limit_mode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceLimiterMode {
+    /// The default [`SourceRateLimiter`] token bucket.
+    TokenBucket,
+    /// [`GcraLimiter`] keyed on the exact source address.
+    GcraExact,
+    /// [`GcraLimiter`] keyed on the containing /24 (IPv4) or /64 (IPv6), so
+    /// an abusive subnet is throttled as a whole instead of just whichever
+    /// address within it happens to be hitting the limit.
+    GcraSubnet,
+}
+
+impl SourceLimiterMode {
+    pub fn from_str(s: &str) -> Self {
+        match s {
+            "gcra" | "gcra_exact" => SourceLimiterMode::GcraExact,
+            "gcra_subnet" => SourceLimiterMode::GcraSubnet,
+            _ => SourceLimiterMode::TokenBucket,
+        }
+    }
+}
+
+/// Per-source-IP limiter behind a single `allow(ip)` call, backed by
+/// whichever strategy `SourceLimiterMode` selects. `ProxyState` holds one of
+/// these behind an `Arc` and swaps it wholesale on `update_config`, the same
+/// way it swaps `circuit_breaker` and `rate_limiter`.
+pub enum SourceLimiter {
+    TokenBucket(SourceRateLimiter),
+    Gcra(GcraLimiter),
+}
+
+impl SourceLimiter {
+    pub fn new(
+        mode: SourceLimiterMode,
+        rps: f64,
+        burst: f64,
+        max_entries: usize,
+        ttl: Duration,
+    ) -> Self {
+        match mode {
+            SourceLimiterMode::TokenBucket => {
+                SourceLimiter::TokenBucket(SourceRateLimiter::new(rps, burst, max_entries, ttl))
+            }
+            SourceLimiterMode::GcraExact => SourceLimiter::Gcra(GcraLimiter::new(
+                rps,
+                burst as u32,
+                GcraKeyStrategy::Exact,
+                max_entries,
+                ttl,
+            )),
+            SourceLimiterMode::GcraSubnet => SourceLimiter::Gcra(GcraLimiter::new(
+                rps,
+                burst as u32,
+                GcraKeyStrategy::Subnet,
+                max_entries,
+                ttl,
+            )),
+        }
+    }
+
+    /// Check and consume for `ip`, allowing or denying the request.
+    pub fn allow(&self, ip: IpAddr) -> bool {
+        match self {
+            SourceLimiter::TokenBucket(limiter) => limiter.allow(ip),
+            SourceLimiter::Gcra(limiter) => limiter.allow(ip),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -187,4 +517,156 @@ mod tests {
         // Connection 2 should still work
         assert!(limiter.allow_request(Some("conn2")));
     }
+
+    #[test]
+    fn test_source_rate_limiter_basic() {
+        let limiter = SourceRateLimiter::new(10.0, 5.0, 100, Duration::from_secs(60));
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+
+        for _ in 0..5 {
+            assert!(limiter.allow(ip));
+        }
+
+        assert!(!limiter.allow(ip));
+    }
+
+    #[test]
+    fn test_source_rate_limiter_per_ip() {
+        let limiter = SourceRateLimiter::new(10.0, 2.0, 100, Duration::from_secs(60));
+        let ip1: IpAddr = "10.0.0.1".parse().unwrap();
+        let ip2: IpAddr = "10.0.0.2".parse().unwrap();
+
+        assert!(limiter.allow(ip1));
+        assert!(limiter.allow(ip1));
+        assert!(!limiter.allow(ip1));
+
+        // A different source IP has its own bucket
+        assert!(limiter.allow(ip2));
+    }
+
+    #[test]
+    fn test_source_rate_limiter_sheds_when_full() {
+        let limiter = SourceRateLimiter::new(10.0, 5.0, 1, Duration::from_secs(60));
+        let ip1: IpAddr = "10.0.0.1".parse().unwrap();
+        let ip2: IpAddr = "10.0.0.2".parse().unwrap();
+
+        assert!(limiter.allow(ip1));
+        assert_eq!(limiter.tracked_sources(), 1);
+
+        // Table is at capacity; a new unknown source is shed, not inserted.
+        assert!(!limiter.allow(ip2));
+        assert_eq!(limiter.tracked_sources(), 1);
+    }
+
+    #[test]
+    fn test_gcra_allows_burst_then_rejects() {
+        let limiter = GcraLimiter::new(
+            10.0,
+            3,
+            GcraKeyStrategy::Exact,
+            100,
+            Duration::from_secs(60),
+        );
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+
+        // A burst of 3 (tolerance of 2 intervals) should admit a 3-request burst.
+        for _ in 0..3 {
+            assert!(limiter.allow(ip));
+        }
+
+        assert!(!limiter.allow(ip));
+    }
+
+    #[test]
+    fn test_gcra_refills_over_time() {
+        let limiter = GcraLimiter::new(
+            50.0,
+            1,
+            GcraKeyStrategy::Exact,
+            100,
+            Duration::from_secs(60),
+        );
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+
+        assert!(limiter.allow(ip));
+        assert!(!limiter.allow(ip));
+
+        // Emission interval is 20ms; after waiting longer than that the
+        // TAT should have caught up and the next request should pass.
+        thread::sleep(Duration::from_millis(30));
+        assert!(limiter.allow(ip));
+    }
+
+    #[test]
+    fn test_gcra_subnet_aggregation() {
+        let limiter = GcraLimiter::new(
+            10.0,
+            1,
+            GcraKeyStrategy::Subnet,
+            100,
+            Duration::from_secs(60),
+        );
+        let ip1: IpAddr = "10.0.0.1".parse().unwrap();
+        let ip2: IpAddr = "10.0.0.2".parse().unwrap();
+
+        // Same /24, so the two addresses share one TAT.
+        assert!(limiter.allow(ip1));
+        assert!(!limiter.allow(ip2));
+        assert_eq!(limiter.tracked_keys(), 1);
+    }
+
+    #[test]
+    fn test_gcra_exact_keys_are_independent() {
+        let limiter = GcraLimiter::new(
+            10.0,
+            1,
+            GcraKeyStrategy::Exact,
+            100,
+            Duration::from_secs(60),
+        );
+        let ip1: IpAddr = "10.0.0.1".parse().unwrap();
+        let ip2: IpAddr = "10.0.0.2".parse().unwrap();
+
+        assert!(limiter.allow(ip1));
+        assert!(limiter.allow(ip2));
+        assert_eq!(limiter.tracked_keys(), 2);
+    }
+
+    #[test]
+    fn test_source_limiter_mode_from_str() {
+        assert_eq!(SourceLimiterMode::from_str("token_bucket"), SourceLimiterMode::TokenBucket);
+        assert_eq!(SourceLimiterMode::from_str("unknown"), SourceLimiterMode::TokenBucket);
+        assert_eq!(SourceLimiterMode::from_str("gcra"), SourceLimiterMode::GcraExact);
+        assert_eq!(SourceLimiterMode::from_str("gcra_subnet"), SourceLimiterMode::GcraSubnet);
+    }
+
+    #[test]
+    fn test_source_limiter_gcra_subnet_mode_aggregates_by_subnet() {
+        let limiter = SourceLimiter::new(
+            SourceLimiterMode::GcraSubnet,
+            10.0,
+            1.0,
+            100,
+            Duration::from_secs(60),
+        );
+        let ip1: IpAddr = "10.0.0.1".parse().unwrap();
+        let ip2: IpAddr = "10.0.0.2".parse().unwrap();
+
+        // Same /24, so the two addresses share one TAT under GCRA subnet mode.
+        assert!(limiter.allow(ip1));
+        assert!(!limiter.allow(ip2));
+    }
+
+    #[test]
+    fn test_gcra_sheds_when_full() {
+        let limiter = GcraLimiter::new(10.0, 1, GcraKeyStrategy::Exact, 1, Duration::from_secs(60));
+        let ip1: IpAddr = "10.0.0.1".parse().unwrap();
+        let ip2: IpAddr = "10.0.0.2".parse().unwrap();
+
+        assert!(limiter.allow(ip1));
+        assert_eq!(limiter.tracked_keys(), 1);
+
+        assert!(!limiter.allow(ip2));
+        assert_eq!(limiter.tracked_keys(), 1);
+    }
 }
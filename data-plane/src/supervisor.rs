@@ -0,0 +1,92 @@
+use dashmap::DashMap;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task::AbortHandle;
+use tracing::error;
+
+use crate::metrics::MetricsCollector;
+
+/// Owns every spawned connection task in place of bare `tokio::spawn`, so
+/// the proxy has a single place to enforce max-in-flight backpressure,
+/// track `JoinHandle`s for graceful/forced shutdown, and observe panics
+/// that would otherwise vanish silently along with a discarded `JoinHandle`.
+pub struct ConnectionSupervisor {
+    semaphore: Arc<Semaphore>,
+    tasks: DashMap<u64, AbortHandle>,
+    task_counter: parking_lot::Mutex<u64>,
+    metrics: Arc<MetricsCollector>,
+}
+
+impl ConnectionSupervisor {
+    pub fn new(max_in_flight: usize, metrics: Arc<MetricsCollector>) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(max_in_flight)),
+            tasks: DashMap::new(),
+            task_counter: parking_lot::Mutex::new(0),
+            metrics,
+        }
+    }
+
+    /// Reserve a concurrency slot, waiting for one to free up if the
+    /// supervisor is saturated. This is the backpressure point: callers are
+    /// expected to await it before accepting the next connection, so a
+    /// saturated supervisor backs up the listener's accept queue instead of
+    /// completing a handshake it has no capacity to serve.
+    pub async fn acquire(self: &Arc<Self>) -> OwnedSemaphorePermit {
+        self.semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("supervisor semaphore is never closed")
+    }
+
+    /// Number of connection tasks currently supervised.
+    pub fn live_task_count(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Spawn `future` as a supervised connection task. `permit` is held for
+    /// the task's lifetime and dropped when it ends, freeing its
+    /// concurrency slot. The task is tracked so `abort_all` can reach it,
+    /// and a panic is recorded through `MetricsCollector` instead of being
+    /// silently lost.
+    pub fn spawn<F>(self: &Arc<Self>, permit: OwnedSemaphorePermit, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let task_id = {
+            let mut counter = self.task_counter.lock();
+            *counter += 1;
+            *counter
+        };
+
+        let join_handle = tokio::spawn(async move {
+            let _permit = permit;
+            future.await;
+        });
+
+        self.tasks.insert(task_id, join_handle.abort_handle());
+
+        let supervisor = self.clone();
+        tokio::spawn(async move {
+            match join_handle.await {
+                Ok(()) => {}
+                Err(e) if e.is_cancelled() => {}
+                Err(e) => {
+                    error!("Connection task panicked: {}", e);
+                    supervisor.metrics.record_task_panic();
+                }
+            }
+            supervisor.tasks.remove(&task_id);
+        });
+    }
+
+    /// Abort every still-running supervised task. Used once the drain
+    /// deadline passes and stragglers need to be force-cancelled.
+    pub fn abort_all(&self) {
+        for entry in self.tasks.iter() {
+            entry.value().abort();
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use std::os::unix::io::RawFd;
+
+/// Snapshot of kernel-reported transport health for a TCP socket.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TcpInfo {
+    pub rtt_us: u32,
+    pub rtt_var_us: u32,
+    pub retransmits: u32,
+}
+
+/// Read `TCP_INFO` for an established socket, giving real transport-level
+/// RTT and retransmit counts without synthetic probing.
+#[cfg(target_os = "linux")]
+pub fn read_tcp_info(fd: RawFd) -> Option<TcpInfo> {
+    use std::mem;
+
+    let mut info: libc::tcp_info = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<libc::tcp_info>() as libc::socklen_t;
+
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::IPPROTO_TCP,
+            libc::TCP_INFO,
+            &mut info as *mut _ as *mut libc::c_void,
+            &mut len,
+        )
+    };
+
+    if ret != 0 {
+        return None;
+    }
+
+    Some(TcpInfo {
+        rtt_us: info.tcpi_rtt,
+        rtt_var_us: info.tcpi_rttvar,
+        retransmits: info.tcpi_total_retrans,
+    })
+}
+
+/// `TCP_INFO` is Linux-specific; other platforms have no equivalent socket
+/// option, so sampling is a no-op there.
+#[cfg(not(target_os = "linux"))]
+pub fn read_tcp_info(_fd: RawFd) -> Option<TcpInfo> {
+    None
+}
@@ -1,4 +1,8 @@
+use socket2::{Domain, SockRef, Socket, TcpKeepalive, Type};
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
 use tracing::{debug, error, info, warn};
@@ -6,29 +10,113 @@ use tracing::{debug, error, info, warn};
 use crate::config::ProxyState;
 use crate::load_balancer::LoadBalancer;
 
-pub async fn run(state: Arc<ProxyState>) -> Result<(), Box<dyn std::error::Error>> {
-    let config = state.get_config().ok_or("Proxy not configured")?;
+/// Server-side TCP keep-alive tuning. Applied via `socket2` to both the
+/// accepted client connection and the backend connection.
+#[derive(Debug, Clone, Copy)]
+pub struct TcpKeepaliveConfig {
+    pub idle_secs: u64,
+    pub interval_secs: u64,
+    pub count: u32,
+}
+
+/// Bind the TCP listening socket. Split out from `run` so the caller can
+/// bind every privileged port up front and drop privileges before any
+/// connection is accepted.
+///
+/// Built through `socket2` rather than `TcpListener::bind` directly so we
+/// can set `SO_REUSEADDR` and, where supported, TCP Fast Open before the
+/// socket starts listening.
+pub async fn bind(config: &crate::config::ProxyConfig) -> Result<TcpListener, Box<dyn std::error::Error>> {
+    let addr: std::net::SocketAddr = config.tcp_address.parse()?;
+    let domain = if addr.is_ipv6() { Domain::IPV6 } else { Domain::IPV4 };
+
+    let socket = Socket::new(domain, Type::STREAM, None)?;
+    socket.set_reuse_address(true)?;
+
+    if config.tcp_fast_open {
+        if let Err(e) = set_tcp_fast_open(&socket) {
+            warn!("Failed to enable TCP Fast Open: {}", e);
+        }
+    }
 
-    let listener = TcpListener::bind(&config.tcp_address).await?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    socket.set_nonblocking(true)?;
+
+    let listener = TcpListener::from_std(socket.into())?;
     info!("TCP proxy listening on {}", config.tcp_address);
+    Ok(listener)
+}
+
+#[cfg(target_os = "linux")]
+fn set_tcp_fast_open(socket: &Socket) -> std::io::Result<()> {
+    let queue_len: libc::c_int = 5;
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_FASTOPEN,
+            &queue_len as *const _ as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_tcp_fast_open(_socket: &Socket) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Apply server-side keep-alive settings to an already-connected stream.
+fn apply_keepalive(stream: &TcpStream, keepalive: &TcpKeepaliveConfig) -> std::io::Result<()> {
+    let params = TcpKeepalive::new()
+        .with_time(Duration::from_secs(keepalive.idle_secs))
+        .with_interval(Duration::from_secs(keepalive.interval_secs));
+
+    #[cfg(target_os = "linux")]
+    let params = params.with_retries(keepalive.count);
+
+    SockRef::from(stream).set_tcp_keepalive(&params)
+}
 
-    let load_balancer = Arc::new(LoadBalancer::new(
-        config.backends.clone(),
-        config.algorithm.clone(),
-    ));
+pub async fn run(
+    listener: TcpListener,
+    state: Arc<ProxyState>,
+    load_balancer: Arc<LoadBalancer>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config = state.get_config().ok_or("Proxy not configured")?;
+    let mut shutdown_rx = state.subscribe_shutdown();
 
     loop {
-        // Check if draining
-        if state.is_draining() {
-            info!("TCP proxy is draining, not accepting new connections");
-            break;
-        }
+        // Wait for a concurrency slot before accepting the next connection,
+        // so a saturated supervisor backs up the kernel accept queue
+        // instead of completing handshakes it can't serve.
+        let connection_supervisor = state.connection_supervisor();
+        let permit = tokio::select! {
+            permit = connection_supervisor.acquire() => permit,
+            _ = shutdown_rx.changed() => {
+                info!("TCP proxy is draining, not accepting new connections");
+                break;
+            }
+        };
 
-        let (client_socket, client_addr) = match listener.accept().await {
-            Ok(conn) => conn,
-            Err(e) => {
-                error!("Failed to accept connection: {}", e);
-                continue;
+        let (client_socket, client_addr) = tokio::select! {
+            accept_result = listener.accept() => {
+                match accept_result {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        error!("Failed to accept connection: {}", e);
+                        continue;
+                    }
+                }
+            }
+            _ = shutdown_rx.changed() => {
+                info!("TCP proxy is draining, not accepting new connections");
+                break;
             }
         };
 
@@ -37,10 +125,17 @@ pub async fn run(state: Arc<ProxyState>) -> Result<(), Box<dyn std::error::Error
         let state_clone = state.clone();
         let lb_clone = load_balancer.clone();
         let config_clone = config.clone();
-
-        tokio::spawn(async move {
-            if let Err(e) =
-                handle_connection(client_socket, state_clone, lb_clone, config_clone).await
+        let conn_shutdown_rx = shutdown_rx.clone();
+
+        connection_supervisor.spawn(permit, async move {
+            if let Err(e) = handle_connection(
+                client_socket,
+                state_clone,
+                lb_clone,
+                config_clone,
+                conn_shutdown_rx,
+            )
+            .await
             {
                 error!("Connection error: {}", e);
             }
@@ -55,50 +150,62 @@ async fn handle_connection(
     state: Arc<ProxyState>,
     load_balancer: Arc<LoadBalancer>,
     config: crate::config::ProxyConfig,
+    mut shutdown_rx: tokio::sync::watch::Receiver<bool>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     // Get client address for rate limiting and logging
     let client_addr = client.peer_addr()?;
 
+    if config.tcp_nodelay {
+        if let Err(e) = client.set_nodelay(true) {
+            warn!("Failed to set TCP_NODELAY on client socket: {}", e);
+        }
+    }
+    if let Some(keepalive) = &config.tcp_keepalive {
+        if let Err(e) = apply_keepalive(&client, keepalive) {
+            warn!("Failed to set keep-alive on client socket: {}", e);
+        }
+    }
+
+    // Per-source-IP load shedding, ahead of the per-connection limiter
+    if !state.source_rate_limiter().allow(client_addr.ip()) {
+        warn!("Source rate limit exceeded for IP: {}", client_addr.ip());
+        state.metrics.record_rate_limit_denied();
+        return Err("Source rate limit exceeded".into());
+    }
+
     // Check rate limit
     if !state
-        .rate_limiter
+        .rate_limiter()
         .allow_request(Some(&client_addr.to_string()))
     {
         warn!("Rate limit exceeded for client: {}", client_addr);
         state.metrics.record_rate_limit_denied();
         return Err("Rate limit exceeded".into());
     }
-    
+
     state.metrics.record_rate_limit_allowed();
     state.metrics.record_tcp_connection();
 
-    // Register connection
-    let (conn_id, _token) = state.register_connection();
-
-    // Ensure we unregister on drop
-    let _guard = ConnectionGuard {
-        state: state.clone(),
-        conn_id,
-    };
-
     // Select backend with consistent hashing support
     let backend = load_balancer
         .select_backend_with_context(Some(&client_addr.ip().to_string()))
         .ok_or("No healthy backends available")?;
 
+    let circuit_breaker = state.circuit_breaker();
+
     // Check circuit breaker
-    if !state.circuit_breaker.allow_request(&backend.address) {
+    if !circuit_breaker.allow_request(&backend.id) {
         warn!(
             "Circuit breaker open for backend: {}, rejecting request",
             backend.address
         );
         state.metrics.record_circuit_breaker_open();
-        state.metrics.record_backend_failure(&backend.address);
+        state.metrics.record_backend_failure(&backend.id);
         return Err("Circuit breaker open".into());
     }
 
     debug!("Forwarding to backend: {}", backend.address);
-    state.metrics.record_backend_connection(&backend.address);
+    state.metrics.record_backend_connection(&backend.id);
 
     // Track connection in load balancer
     load_balancer.increment_connections(&backend.address);
@@ -119,30 +226,75 @@ async fn handle_connection(
         Ok(Ok(stream)) => {
             let latency = start_time.elapsed().as_secs_f64() * 1000.0;
             debug!("Connected to backend {} in {:.2}ms", backend.address, latency);
-            state.circuit_breaker.record_success(&backend.address);
-            state.metrics.record_backend_request(&backend.address);
+            circuit_breaker.record_success(&backend.id);
+            state.metrics.record_backend_request(&backend.id);
             state.metrics.record_latency(latency);
+            load_balancer.record_latency(&backend.address, latency);
+
+            if config.tcp_nodelay {
+                if let Err(e) = stream.set_nodelay(true) {
+                    warn!("Failed to set TCP_NODELAY on backend socket: {}", e);
+                }
+            }
+            if let Some(keepalive) = &config.tcp_keepalive {
+                if let Err(e) = apply_keepalive(&stream, keepalive) {
+                    warn!("Failed to set keep-alive on backend socket: {}", e);
+                }
+            }
+
             stream
         }
         Ok(Err(e)) => {
             error!("Failed to connect to backend {}: {}", backend.address, e);
-            state.circuit_breaker.record_failure(&backend.address);
-            state.metrics.record_backend_failure(&backend.address);
+            circuit_breaker.record_failure(&backend.id);
+            state.metrics.record_backend_failure(&backend.id);
             return Err(e.into());
         }
         Err(_) => {
             error!("Timeout connecting to backend {}", backend.address);
-            state.circuit_breaker.record_failure(&backend.address);
-            state.metrics.record_backend_failure(&backend.address);
+            circuit_breaker.record_failure(&backend.id);
+            state.metrics.record_backend_failure(&backend.id);
             return Err("Connection timeout".into());
         }
     };
 
+    // Periodically sample TCP_INFO off the backend socket for as long as the
+    // connection is live, folding RTT and retransmits into the metrics.
+    // `last_retransmits` is shared with the post-close sample below so the
+    // final snapshot still reports only the delta since the last reading.
+    let backend_fd = backend_stream.as_raw_fd();
+    let sampler_state = state.clone();
+    let last_retransmits = Arc::new(AtomicU64::new(0));
+    let sampler_last_retransmits = last_retransmits.clone();
+    // Wrapped in a drop guard rather than held as a bare `JoinHandle`: if
+    // `ConnectionSupervisor::abort_all` force-cancels this connection's
+    // outer task on the drain deadline, this future is dropped without
+    // ever reaching the `drop(tcp_info_sampler)` below. The guard's `Drop`
+    // still runs in that case, aborting the sampler instead of leaking a
+    // task that keeps polling `backend_fd` after the OS is free to reuse it.
+    let tcp_info_sampler = TaskAbortGuard(tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(5));
+        loop {
+            interval.tick().await;
+            if let Some(info) = crate::tcp_info::read_tcp_info(backend_fd) {
+                sampler_state.metrics.record_latency(info.rtt_us as f64 / 1000.0);
+
+                // tcpi_total_retrans is cumulative; report only the delta.
+                let total = info.retransmits as u64;
+                let previous = sampler_last_retransmits.swap(total, Ordering::Relaxed);
+                let delta = total.saturating_sub(previous);
+                if delta > 0 {
+                    sampler_state.metrics.record_tcp_retransmits(delta);
+                }
+            }
+        }
+    }));
+
     // Split streams for bidirectional copying
     let (mut client_read, mut client_write) = client.split();
     let (mut backend_read, mut backend_write) = backend_stream.split();
-    
-    let backend_addr_clone = backend.address.clone();
+
+    let backend_id_clone = backend.id.clone();
     let state_clone = state.clone();
 
     // Bidirectional copy
@@ -153,7 +305,7 @@ async fn handle_connection(
             let n = match client_read.read(&mut buf).await {
                 Ok(0) => {
                     state_clone.metrics.record_bytes_sent(total_bytes);
-                    state_clone.metrics.record_backend_bytes_sent(&backend_addr_clone, total_bytes);
+                    state_clone.metrics.record_backend_bytes_sent(&backend_id_clone, total_bytes);
                     return Ok::<_, std::io::Error>(());
                 }
                 Ok(n) => n,
@@ -164,8 +316,8 @@ async fn handle_connection(
             backend_write.write_all(&buf[..n]).await?;
         }
     };
-    
-    let backend_addr_clone2 = backend.address.clone();
+
+    let backend_id_clone2 = backend.id.clone();
     let state_clone2 = state.clone();
 
     let backend_to_client = async {
@@ -175,7 +327,7 @@ async fn handle_connection(
             let n = match backend_read.read(&mut buf).await {
                 Ok(0) => {
                     state_clone2.metrics.record_bytes_received(total_bytes);
-                    state_clone2.metrics.record_backend_bytes_received(&backend_addr_clone2, total_bytes);
+                    state_clone2.metrics.record_backend_bytes_received(&backend_id_clone2, total_bytes);
                     return Ok::<_, std::io::Error>(());
                 }
                 Ok(n) => n,
@@ -192,21 +344,42 @@ async fn handle_connection(
         result = client_to_backend => {
             if let Err(e) = result {
                 warn!("Client to backend error: {}", e);
-                state.circuit_breaker.record_failure(&backend.address);
-                state.metrics.record_backend_failure(&backend.address);
+                circuit_breaker.record_failure(&backend.id);
+                state.metrics.record_backend_failure(&backend.id);
             }
         }
         result = backend_to_client => {
             if let Err(e) = result {
                 warn!("Backend to client error: {}", e);
-                state.circuit_breaker.record_failure(&backend.address);
-                state.metrics.record_backend_failure(&backend.address);
+                circuit_breaker.record_failure(&backend.id);
+                state.metrics.record_backend_failure(&backend.id);
             }
         }
+        _ = shutdown_rx.changed() => {
+            debug!(
+                "Shutdown signal received, terminating connection to backend {}",
+                backend.address
+            );
+        }
+    }
+
+    drop(tcp_info_sampler);
+
+    // Final TCP_INFO snapshot now that the connection is closing, so a
+    // connection that never lived past one sampling tick still reports its
+    // RTT/retransmit signal before the backend socket goes away.
+    if let Some(info) = crate::tcp_info::read_tcp_info(backend_fd) {
+        state.metrics.record_latency(info.rtt_us as f64 / 1000.0);
+        let total = info.retransmits as u64;
+        let previous = last_retransmits.swap(total, Ordering::Relaxed);
+        let delta = total.saturating_sub(previous);
+        if delta > 0 {
+            state.metrics.record_tcp_retransmits(delta);
+        }
     }
 
     // Connection completed successfully
-    state.circuit_breaker.record_success(&backend.address);
+    circuit_breaker.record_success(&backend.id);
     state.metrics.close_tcp_connection();
     debug!("Connection closed");
 
@@ -216,24 +389,24 @@ async fn handle_connection(
     Ok(())
 }
 
-struct LoadBalancerGuard {
-    load_balancer: Arc<LoadBalancer>,
-    backend_addr: String,
-}
+/// Aborts the wrapped task when dropped, whether that's an explicit
+/// `drop(...)` or the guard simply going out of scope because its owning
+/// future was cancelled.
+struct TaskAbortGuard(tokio::task::JoinHandle<()>);
 
-impl Drop for LoadBalancerGuard {
+impl Drop for TaskAbortGuard {
     fn drop(&mut self) {
-        self.load_balancer.decrement_connections(&self.backend_addr);
+        self.0.abort();
     }
 }
 
-struct ConnectionGuard {
-    state: Arc<ProxyState>,
-    conn_id: u64,
+struct LoadBalancerGuard {
+    load_balancer: Arc<LoadBalancer>,
+    backend_addr: String,
 }
 
-impl Drop for ConnectionGuard {
+impl Drop for LoadBalancerGuard {
     fn drop(&mut self) {
-        self.state.unregister_connection(self.conn_id);
+        self.load_balancer.decrement_connections(&self.backend_addr);
     }
 }
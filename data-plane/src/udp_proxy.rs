@@ -1,5 +1,9 @@
+use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
-use std::net::SocketAddr;
+use parking_lot::RwLock;
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::net::UdpSocket;
@@ -12,6 +16,103 @@ const SESSION_TIMEOUT: Duration = Duration::from_secs(60);
 const BUFFER_SIZE: usize = 65536;
 const CLEANUP_INTERVAL: Duration = Duration::from_secs(10);
 
+/// Per-client-IP ceiling on concurrent UDP sessions (NAT mappings), to keep
+/// a single abusive or spoofed source from exhausting the session table.
+const MAX_SESSIONS_PER_IP: usize = 64;
+/// Global ceiling on concurrent UDP sessions across all client IPs.
+const MAX_SESSIONS_TOTAL: usize = 100_000;
+
+/// Width of the anti-replay bitmap, in bits. Packets this many or more
+/// counters behind the highest accepted one are always rejected as too old.
+const REPLAY_WINDOW_BITS: usize = 2048;
+const REPLAY_WINDOW_WORDS: usize = REPLAY_WINDOW_BITS / 64;
+
+/// WireGuard-style sliding-window anti-replay filter.
+///
+/// Tracks the highest accepted counter (`last`) plus a bitmap of which of
+/// the preceding `REPLAY_WINDOW_BITS` counters have already been seen, so
+/// duplicated or replayed datagrams can be rejected in O(1) without storing
+/// every counter ever observed. Bit 0 always represents `last` itself.
+struct ReplayFilter {
+    last: u64,
+    window: [u64; REPLAY_WINDOW_WORDS],
+}
+
+impl ReplayFilter {
+    fn new() -> Self {
+        Self {
+            last: 0,
+            window: [0; REPLAY_WINDOW_WORDS],
+        }
+    }
+
+    /// Returns `true` if the packet carrying counter `seq` should be accepted.
+    fn check(&mut self, seq: u64) -> bool {
+        if seq > self.last {
+            let advance = seq - self.last;
+            if advance as usize >= REPLAY_WINDOW_BITS {
+                self.window = [0; REPLAY_WINDOW_WORDS];
+            } else {
+                self.shift(advance as usize);
+            }
+            self.last = seq;
+            self.set_bit(0);
+            true
+        } else {
+            let age = self.last - seq;
+            if age as usize >= REPLAY_WINDOW_BITS {
+                // Too old to be represented in the window at all.
+                false
+            } else if self.test_bit(age as usize) {
+                // Already seen at this position: replay.
+                false
+            } else {
+                self.set_bit(age as usize);
+                true
+            }
+        }
+    }
+
+    /// Age every tracked bit by `shift` positions, dropping ones that fall
+    /// off the oldest end and clearing the vacated low bits.
+    fn shift(&mut self, shift: usize) {
+        if shift == 0 {
+            return;
+        }
+        if shift >= REPLAY_WINDOW_BITS {
+            self.window = [0; REPLAY_WINDOW_WORDS];
+            return;
+        }
+
+        let word_shift = shift / 64;
+        let bit_shift = shift % 64;
+
+        if word_shift > 0 {
+            for i in (word_shift..REPLAY_WINDOW_WORDS).rev() {
+                self.window[i] = self.window[i - word_shift];
+            }
+            for i in 0..word_shift {
+                self.window[i] = 0;
+            }
+        }
+
+        if bit_shift > 0 {
+            for i in (1..REPLAY_WINDOW_WORDS).rev() {
+                self.window[i] = (self.window[i] << bit_shift) | (self.window[i - 1] >> (64 - bit_shift));
+            }
+            self.window[0] <<= bit_shift;
+        }
+    }
+
+    fn set_bit(&mut self, pos: usize) {
+        self.window[pos / 64] |= 1u64 << (pos % 64);
+    }
+
+    fn test_bit(&self, pos: usize) -> bool {
+        (self.window[pos / 64] >> (pos % 64)) & 1 == 1
+    }
+}
+
 /// NAT mapping for UDP sessions with bidirectional tracking
 struct UdpSession {
     backend_addr: String,
@@ -22,6 +123,11 @@ struct UdpSession {
     bytes_received: u64,
     packets_sent: u64,
     packets_received: u64,
+    replay: ReplayFilter,
+    // Stamped on the most recent client->backend forward, consumed (and
+    // cleared) by the next backend->client reply to estimate RTT for the
+    // least-RTT load balancing algorithm.
+    last_send_at: Option<Instant>,
 }
 
 impl UdpSession {
@@ -39,9 +145,23 @@ impl UdpSession {
             bytes_received: 0,
             packets_sent: 0,
             packets_received: 0,
+            replay: ReplayFilter::new(),
+            last_send_at: None,
         }
     }
 
+    /// Anti-replay check for a client->backend packet carrying a sequence
+    /// counter in its leading 8 bytes (big-endian), WireGuard-style.
+    fn check_replay(&mut self, packet: &[u8]) -> bool {
+        if packet.len() < 8 {
+            // No counter present; nothing to check against.
+            return true;
+        }
+        let mut seq_bytes = [0u8; 8];
+        seq_bytes.copy_from_slice(&packet[..8]);
+        self.replay.check(u64::from_be_bytes(seq_bytes))
+    }
+
     fn update_activity(&mut self) {
         self.last_activity = Instant::now();
     }
@@ -53,40 +173,171 @@ impl UdpSession {
     fn record_sent(&mut self, bytes: u64) {
         self.bytes_sent += bytes;
         self.packets_sent += 1;
+        self.last_send_at = Some(Instant::now());
         self.update_activity();
     }
 
-    fn record_received(&mut self, bytes: u64) {
+    /// Records a backend->client reply and, if a forward is still
+    /// outstanding, returns the elapsed time as an RTT sample.
+    fn record_received(&mut self, bytes: u64) -> Option<Duration> {
         self.bytes_received += bytes;
         self.packets_received += 1;
         self.update_activity();
+        self.last_send_at.take().map(|sent_at| sent_at.elapsed())
     }
 }
 
-pub async fn run(state: Arc<ProxyState>) -> Result<(), Box<dyn std::error::Error>> {
-    let config = state.get_config().ok_or("Proxy not configured")?;
-
+/// Bind the UDP socket, or return `None` if the UDP proxy is disabled.
+/// Split out from `run` so the caller can bind every privileged port up
+/// front and drop privileges before any packet is processed.
+pub async fn bind(
+    config: &crate::config::ProxyConfig,
+) -> Result<Option<UdpSocket>, Box<dyn std::error::Error>> {
     if config.udp_address.is_empty() {
         info!("UDP proxy disabled (no address configured)");
-        return Ok(());
+        return Ok(None);
     }
 
-    let socket = Arc::new(UdpSocket::bind(&config.udp_address).await?);
+    let socket = UdpSocket::bind(&config.udp_address).await?;
     info!("UDP proxy listening on {}", config.udp_address);
+    Ok(Some(socket))
+}
 
-    let load_balancer = Arc::new(LoadBalancer::new(
-        config.backends.clone(),
-        config.algorithm.clone(),
-    ));
+pub async fn run(
+    socket: Option<UdpSocket>,
+    state: Arc<ProxyState>,
+    load_balancer: Arc<LoadBalancer>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let socket = match socket {
+        Some(s) => Arc::new(s),
+        None => return Ok(()),
+    };
+
+    // Held behind a lock (rather than rebuilt from scratch per-packet) so a
+    // backend reload or algorithm change can be swapped in atomically without
+    // restarting this listener.
+    let load_balancer: Arc<RwLock<Arc<LoadBalancer>>> = Arc::new(RwLock::new(load_balancer));
 
     // Session tracking with NAT mapping
     let sessions: Arc<DashMap<String, UdpSession>> = Arc::new(DashMap::new());
     // Reverse mapping for backend -> client lookups
     let reverse_sessions: Arc<DashMap<SocketAddr, String>> = Arc::new(DashMap::new());
+    // Live session count per client IP, enforcing MAX_SESSIONS_PER_IP
+    let session_counts: Arc<DashMap<IpAddr, usize>> = Arc::new(DashMap::new());
+
+    // Anti-replay is opt-in (see `ProxyConfig::udp_replay_protection`)
+    // since it assumes every datagram's first 8 bytes are a monotonic
+    // counter, which isn't true for generic UDP traffic. Tracked as an
+    // `AtomicBool` rather than re-fetching and cloning the whole config on
+    // every packet; refreshed by the reconfig task below.
+    let replay_protection = Arc::new(AtomicBool::new(
+        state
+            .get_config()
+            .map(|c| c.udp_replay_protection)
+            .unwrap_or(false),
+    ));
+
+    // Config reconfiguration task - swaps in a new load balancer whenever
+    // the control plane pushes an update, and tears down any NAT mapping
+    // that points at a backend the new config no longer knows about.
+    let reconfig_lb = load_balancer.clone();
+    let reconfig_sessions = sessions.clone();
+    let reconfig_reverse_sessions = reverse_sessions.clone();
+    let reconfig_state = state.clone();
+    let reconfig_replay_protection = replay_protection.clone();
+    let reconfig_session_counts = session_counts.clone();
+    let reconfig_metrics = state.metrics.clone();
+    let mut config_rx = state.subscribe_config();
+    let startup_config = state.get_config();
+    tokio::spawn(async move {
+        // Snapshot of whatever last actually drove a load-balancer rebuild,
+        // so an unrelated config push (ACL update, privdrop tweak, ...)
+        // doesn't throw away accumulated least_rtt/peak_ewma latency
+        // history and active-connection counts for no reason. Seeded from
+        // the config the passed-in load balancer was built from.
+        let mut previous_algorithm = startup_config
+            .as_ref()
+            .map(|c| c.algorithm.clone())
+            .unwrap_or_default();
+        let mut previous_backends: Vec<(String, i32, bool)> = startup_config
+            .iter()
+            .flat_map(|c| c.backends.iter())
+            .map(|b| (b.address.clone(), b.weight, b.healthy))
+            .collect();
+
+        while config_rx.changed().await.is_ok() {
+            let Some(config) = reconfig_state.get_config() else {
+                continue;
+            };
+
+            reconfig_replay_protection.store(config.udp_replay_protection, Ordering::Relaxed);
+
+            let current_backends: Vec<(String, i32, bool)> = config
+                .backends
+                .iter()
+                .map(|b| (b.address.clone(), b.weight, b.healthy))
+                .collect();
+            let algorithm_changed = config.algorithm != previous_algorithm;
+            let backends_changed = current_backends != previous_backends;
+
+            if !algorithm_changed && !backends_changed {
+                continue;
+            }
+
+            if algorithm_changed {
+                *reconfig_lb.write() = Arc::new(LoadBalancer::with_max_backend_rtt(
+                    config.backends.clone(),
+                    config.algorithm.clone(),
+                    Duration::from_millis(config.max_backend_rtt_ms),
+                ));
+            } else {
+                // Only the backend set moved; update the existing load
+                // balancer in place instead of replacing it.
+                reconfig_lb.read().update_backends(config.backends.clone());
+            }
+
+            previous_algorithm = config.algorithm.clone();
+            previous_backends = current_backends;
+
+            let live_backends: HashSet<&str> =
+                config.backends.iter().map(|b| b.address.as_str()).collect();
+
+            let stale_keys: Vec<String> = reconfig_sessions
+                .iter()
+                .filter(|entry| !live_backends.contains(entry.value().backend_addr.as_str()))
+                .map(|entry| entry.key().clone())
+                .collect();
+
+            for key in stale_keys {
+                if let Some((_, session)) = reconfig_sessions.remove(&key) {
+                    reconfig_reverse_sessions.remove(&session.backend_socket_addr);
+                    reconfig_metrics.close_udp_session();
+
+                    let ip = session.client_addr.ip();
+                    if let Some(mut count) = reconfig_session_counts.get_mut(&ip) {
+                        *count = count.saturating_sub(1);
+                        let exhausted = *count == 0;
+                        drop(count);
+                        if exhausted {
+                            reconfig_session_counts.remove(&ip);
+                        }
+                    }
+                }
+            }
+
+            info!(
+                "UDP load balancer reconfigured: {} backends, algorithm={}",
+                config.backends.len(),
+                config.algorithm
+            );
+        }
+    });
 
     // Session cleanup task - removes expired sessions
     let sessions_clone = sessions.clone();
     let reverse_sessions_clone = reverse_sessions.clone();
+    let session_counts_clone = session_counts.clone();
+    let cleanup_metrics = state.metrics.clone();
     tokio::spawn(async move {
         let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
         loop {
@@ -114,25 +365,39 @@ pub async fn run(state: Arc<ProxyState>) -> Result<(), Box<dyn std::error::Error
             for key in expired_keys {
                 if let Some((_, session)) = sessions_clone.remove(&key) {
                     reverse_sessions_clone.remove(&session.backend_socket_addr);
+                    cleanup_metrics.close_udp_session();
+
+                    let ip = session.client_addr.ip();
+                    if let Some(mut count) = session_counts_clone.get_mut(&ip) {
+                        *count = count.saturating_sub(1);
+                        let exhausted = *count == 0;
+                        drop(count);
+                        if exhausted {
+                            session_counts_clone.remove(&ip);
+                        }
+                    }
                 }
             }
         }
     });
 
     let mut buf = vec![0u8; BUFFER_SIZE];
+    let mut shutdown_rx = state.subscribe_shutdown();
 
     loop {
-        // Check if draining
-        if state.is_draining() {
-            info!("UDP proxy is draining");
-            break;
-        }
-
-        let (len, peer_addr) = match socket.recv_from(&mut buf).await {
-            Ok(result) => result,
-            Err(e) => {
-                error!("Failed to receive UDP packet: {}", e);
-                continue;
+        let (len, peer_addr) = tokio::select! {
+            recv_result = socket.recv_from(&mut buf) => {
+                match recv_result {
+                    Ok(result) => result,
+                    Err(e) => {
+                        error!("Failed to receive UDP packet: {}", e);
+                        continue;
+                    }
+                }
+            }
+            _ = shutdown_rx.changed() => {
+                info!("UDP proxy is draining");
+                break;
             }
         };
 
@@ -140,7 +405,12 @@ pub async fn run(state: Arc<ProxyState>) -> Result<(), Box<dyn std::error::Error
         let socket_clone = socket.clone();
         let sessions_clone = sessions.clone();
         let reverse_sessions_clone = reverse_sessions.clone();
-        let lb_clone = load_balancer.clone();
+        let session_counts_clone = session_counts.clone();
+        let lb_clone = load_balancer.read().clone();
+        let metrics_clone = state.metrics.clone();
+        let source_rate_limiter_clone = state.source_rate_limiter();
+        let access_control_clone = state.access_control.clone();
+        let replay_protection_enabled = replay_protection.load(Ordering::Relaxed);
 
         // Process packet asynchronously
         tokio::spawn(async move {
@@ -149,8 +419,16 @@ pub async fn run(state: Arc<ProxyState>) -> Result<(), Box<dyn std::error::Error
                 // Packet from backend to client
                 let client_key_str = client_key.value().clone();
                 if let Some(mut session) = sessions_clone.get_mut(&client_key_str) {
-                    session.record_received(len as u64);
+                    let rtt_sample = session.record_received(len as u64);
                     let client_addr = session.client_addr;
+                    let backend_addr = session.backend_addr.clone();
+
+                    metrics_clone.record_bytes_received(len as u64);
+                    metrics_clone.record_packet_received();
+
+                    if let Some(rtt) = rtt_sample {
+                        lb_clone.record_backend_latency(&backend_addr, rtt);
+                    }
 
                     debug!(
                         "Forwarding {} bytes from backend {} to client {}",
@@ -164,11 +442,68 @@ pub async fn run(state: Arc<ProxyState>) -> Result<(), Box<dyn std::error::Error
                 }
             } else {
                 // Packet from client to backend - establish/update session
+
+                // Reject denied source IPs before any session bookkeeping.
+                if !access_control_clone.check(peer_addr.ip()) {
+                    debug!("Access denied for client IP: {}", peer_addr.ip());
+                    return;
+                }
+
                 let client_key = peer_addr.to_string();
 
-                // Get or create session with NAT mapping
-                let (backend_socket_addr, client_addr) = {
-                    let mut session = sessions_clone.entry(client_key.clone()).or_insert_with(|| {
+                // Soft global cap, checked before taking the per-key entry
+                // lock below - DashMap::len() walks every shard, including
+                // this key's, so it can't be called while that shard's lock
+                // is held without deadlocking.
+                if sessions_clone.len() >= MAX_SESSIONS_TOTAL && !sessions_clone.contains_key(&client_key) {
+                    warn!(
+                        "Global UDP session cap ({}) reached, dropping new session from {}",
+                        MAX_SESSIONS_TOTAL, peer_addr
+                    );
+                    return;
+                }
+
+                // Admission checks (source rate limit, per-IP session cap)
+                // and session creation all happen while holding this entry's
+                // shard lock, so creating a session and counting it toward
+                // the per-IP cap are a single atomic step: two packets
+                // racing in for the same brand-new client can no longer both
+                // observe "no session yet" and double-increment the count.
+                let backend_and_client = match sessions_clone.entry(client_key.clone()) {
+                    Entry::Occupied(mut occupied) => {
+                        let session = occupied.get_mut();
+                        if replay_protection_enabled && !session.check_replay(&packet) {
+                            debug!("Dropping replayed/out-of-window packet from {}", peer_addr);
+                            metrics_clone.record_replay_dropped();
+                            None
+                        } else {
+                            session.record_sent(len as u64);
+                            metrics_clone.record_bytes_sent(len as u64);
+                            metrics_clone.record_packet_sent();
+                            Some((session.backend_socket_addr, session.client_addr))
+                        }
+                    }
+                    Entry::Vacant(vacant) => {
+                        // Load-shed new sessions from sources over their accept rate
+                        if !source_rate_limiter_clone.allow(peer_addr.ip()) {
+                            debug!("Source rate limit exceeded for IP: {}", peer_addr.ip());
+                            metrics_clone.record_rate_limit_denied();
+                            return;
+                        }
+
+                        let ip = peer_addr.ip();
+                        let mut count = session_counts_clone.entry(ip).or_insert(0);
+                        if *count >= MAX_SESSIONS_PER_IP {
+                            warn!(
+                                "UDP session cap ({}) reached for IP {}, dropping new session",
+                                MAX_SESSIONS_PER_IP, ip
+                            );
+                            return;
+                        }
+                        *count += 1;
+                        drop(count);
+                        metrics_clone.record_rate_limit_allowed();
+
                         let backend = lb_clone
                             .select_backend_with_context(Some(&peer_addr.ip().to_string()))
                             .expect("No healthy backends available");
@@ -182,11 +517,29 @@ pub async fn run(state: Arc<ProxyState>) -> Result<(), Box<dyn std::error::Error
                             peer_addr, backend.address
                         );
 
-                        UdpSession::new(backend.address, backend_socket_addr, peer_addr)
-                    });
+                        metrics_clone.record_udp_session();
+                        let mut session = vacant.insert(UdpSession::new(
+                            backend.address,
+                            backend_socket_addr,
+                            peer_addr,
+                        ));
+
+                        if replay_protection_enabled && !session.check_replay(&packet) {
+                            debug!("Dropping replayed/out-of-window packet from {}", peer_addr);
+                            metrics_clone.record_replay_dropped();
+                            None
+                        } else {
+                            session.record_sent(len as u64);
+                            metrics_clone.record_bytes_sent(len as u64);
+                            metrics_clone.record_packet_sent();
+                            Some((session.backend_socket_addr, session.client_addr))
+                        }
+                    }
+                };
 
-                    session.record_sent(len as u64);
-                    (session.backend_socket_addr, session.client_addr)
+                let (backend_socket_addr, client_addr) = match backend_and_client {
+                    Some(v) => v,
+                    None => return,
                 };
 
                 // Update reverse mapping
@@ -222,3 +575,61 @@ pub async fn run(state: Arc<ProxyState>) -> Result<(), Box<dyn std::error::Error
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_filter_accepts_monotonic_counters() {
+        let mut filter = ReplayFilter::new();
+        for seq in 1..=10 {
+            assert!(filter.check(seq), "sequence {} should be accepted", seq);
+        }
+    }
+
+    #[test]
+    fn test_replay_filter_rejects_exact_duplicate() {
+        let mut filter = ReplayFilter::new();
+        assert!(filter.check(5));
+        assert!(!filter.check(5), "replayed counter must be rejected");
+    }
+
+    #[test]
+    fn test_replay_filter_accepts_reordered_within_window() {
+        let mut filter = ReplayFilter::new();
+        assert!(filter.check(10));
+        // Out of order but still inside the window: accepted once.
+        assert!(filter.check(8));
+        // Replaying that same reordered counter is rejected.
+        assert!(!filter.check(8));
+    }
+
+    #[test]
+    fn test_replay_filter_rejects_packet_older_than_window() {
+        let mut filter = ReplayFilter::new();
+        assert!(filter.check(REPLAY_WINDOW_BITS as u64 + 100));
+        // Falls further behind `last` than the window can represent.
+        assert!(!filter.check(1));
+    }
+
+    #[test]
+    fn test_replay_filter_handles_large_forward_jump() {
+        let mut filter = ReplayFilter::new();
+        assert!(filter.check(5));
+        // Advance so far that the whole window is reset rather than shifted.
+        assert!(filter.check(5 + REPLAY_WINDOW_BITS as u64 + 1));
+        // The bit for the new `last` should be set, so an immediate replay
+        // of it is rejected.
+        let last = filter.last;
+        assert!(!filter.check(last));
+    }
+
+    #[test]
+    fn test_replay_filter_first_packet_accepted() {
+        // `last` starts at 0; a first packet with a low counter must still
+        // be accepted rather than treated as already-seen.
+        let mut filter = ReplayFilter::new();
+        assert!(filter.check(0));
+    }
+}